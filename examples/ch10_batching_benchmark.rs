@@ -0,0 +1,176 @@
+//! BatchingStrategy 对比测试, 无头运行
+//! 用不同的实体数量和批处理策略运行 ch10 的 move_system + bounce_system (blibli_bevy2::sprite_motion),
+//! 打印每帧耗时并写入 CSV
+//! 需要用 release 编译, debug 下的数字没有参考意义
+//!
+//! cargo run --release --example ch10_batching_benchmark -- 100 batching_benchmark.csv
+//! 参数依次为 每组测量的帧数 (默认 100) / CSV 路径 (默认 batching_benchmark.csv)
+
+use std::{
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    ecs::batching::BatchingStrategy,
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
+};
+use blibli_bevy2::{
+    bounds::{BoundaryMode, Bounds},
+    sprite_motion::{MotionBatching, Velocity, bounce_system, move_system},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const ENTITY_COUNTS: [usize; 4] = [1_000, 10_000, 100_000, 1_000_000];
+const WARMUP_FRAMES: usize = 10;
+
+// 被测的批处理方式
+#[derive(Debug, Clone, Copy)]
+enum Strategy {
+    // iter_mut, 不并行
+    SingleThreaded,
+    // BatchingStrategy::new(), 由 bevy 根据线程数决定批大小
+    Automatic,
+    // BatchingStrategy::fixed(n)
+    Fixed(usize),
+}
+
+const STRATEGIES: [Strategy; 6] = [
+    Strategy::SingleThreaded,
+    Strategy::Automatic,
+    Strategy::Fixed(32),
+    Strategy::Fixed(256),
+    Strategy::Fixed(4096),
+    Strategy::Fixed(65536),
+];
+
+impl Strategy {
+    fn name(self) -> String {
+        match self {
+            Strategy::SingleThreaded => "single".to_string(),
+            Strategy::Automatic => "new()".to_string(),
+            Strategy::Fixed(size) => format!("fixed({size})"),
+        }
+    }
+
+    fn batching(self) -> Option<BatchingStrategy> {
+        match self {
+            Strategy::SingleThreaded => None,
+            Strategy::Automatic => Some(BatchingStrategy::new()),
+            Strategy::Fixed(size) => Some(BatchingStrategy::fixed(size)),
+        }
+    }
+}
+
+// 一组测量的结果, 单位毫秒
+struct Measurement {
+    entities: usize,
+    strategy: Strategy,
+    mean: f64,
+    median: f64,
+    min: f64,
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let frames = args
+        .next()
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(100usize)
+        .max(1);
+    let path = args
+        .next()
+        .unwrap_or_else(|| "batching_benchmark.csv".to_string());
+
+    let threads = ComputeTaskPool::get_or_init(TaskPool::default).thread_num();
+    println!("threads: {threads}, frames per run: {frames}");
+    println!(
+        "{:>10}  {:>12}  {:>10}  {:>10}  {:>10}",
+        "entities", "strategy", "mean ms", "median ms", "min ms"
+    );
+
+    let mut measurements = Vec::new();
+    for entities in ENTITY_COUNTS {
+        for strategy in STRATEGIES {
+            let measurement = run(entities, strategy, frames);
+            println!(
+                "{:>10}  {:>12}  {:>10.3}  {:>10.3}  {:>10.3}",
+                entities,
+                strategy.name(),
+                measurement.mean,
+                measurement.median,
+                measurement.min
+            );
+            measurements.push(measurement);
+        }
+    }
+
+    let mut csv = String::from("entities,strategy,threads,mean_ms,median_ms,min_ms\n");
+    for m in &measurements {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{}",
+            m.entities,
+            m.strategy.name(),
+            threads,
+            m.mean,
+            m.median,
+            m.min
+        );
+    }
+    match std::fs::write(&path, csv) {
+        Ok(()) => println!("saved {path}"),
+        Err(err) => eprintln!("failed to write {path}: {err}"),
+    }
+}
+
+// 每组使用新的 World, 实体的初始状态与 ch10 一样由固定种子生成
+// 只做反射, 不删除实体, 每帧的实体数量保持不变
+fn run(entities: usize, strategy: Strategy, frames: usize) -> Measurement {
+    let mut world = World::new();
+    let bounds = Bounds {
+        mode: BoundaryMode::Reflect,
+        ..default()
+    };
+    world.insert_resource(bounds);
+    world.insert_resource(MotionBatching(strategy.batching()));
+    let mut rng = ChaCha8Rng::seed_from_u64(19878367467713);
+    world.spawn_batch((0..entities).map(|_| {
+        let v = 20.0 * Vec2::new(rng.random::<f32>() - 0.5, rng.random::<f32>() - 0.5);
+        let position = bounds.rect.min
+            + bounds.rect.size() * Vec2::new(rng.random::<f32>(), rng.random::<f32>());
+        (
+            // 系统只处理精灵, 不加载图片, 不需要渲染
+            Sprite::default(),
+            Transform::from_translation(position.extend(0.0)).with_scale(Vec3::splat(0.1)),
+            Velocity(v),
+        )
+    }));
+
+    let mut schedule = Schedule::default();
+    schedule.add_systems((move_system, bounce_system).chain());
+
+    let mut times: Vec<Duration> = Vec::with_capacity(frames);
+    for frame in 0..WARMUP_FRAMES + frames {
+        let start = Instant::now();
+        schedule.run(&mut world);
+        if frame >= WARMUP_FRAMES {
+            times.push(start.elapsed());
+        }
+    }
+
+    let mut millis: Vec<f64> = times
+        .iter()
+        .map(|time| time.as_secs_f64() * 1000.0)
+        .collect();
+    millis.sort_by(f64::total_cmp);
+    Measurement {
+        entities,
+        strategy,
+        mean: millis.iter().sum::<f64>() / millis.len() as f64,
+        median: millis[millis.len() / 2],
+        min: millis[0],
+    }
+}
@@ -0,0 +1,106 @@
+//! Boids 群体行为, 在 ch10 并行查询的基础上用 par_iter_mut 更新每个 boid
+//! 分离 / 对齐 / 聚集 + 躲避障碍物 + 不飞出窗口, 权重在 inspector 的 BoidSettings 中实时调整
+//! 鼠标左键放置障碍物, 群体指标 (对齐程度、邻居数、速率、分散程度) 打印在日志中
+
+use bevy::{
+    diagnostic::LogDiagnosticsPlugin, input::common_conditions::input_just_pressed, prelude::*,
+};
+use bevy_inspector_egui::{bevy_egui::EguiPlugin, quick::WorldInspectorPlugin};
+use blibli_bevy2::boids::{Boid, BoidSettings, BoidsPlugin, Obstacle};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const NUM_BOIDS: usize = 800;
+const OBSTACLE_RADIUS: f32 = 30.0;
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins(EguiPlugin::default())
+        .add_plugins(WorldInspectorPlugin::new())
+        .add_plugins(LogDiagnosticsPlugin::default())
+        .add_plugins(BoidsPlugin)
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                fit_bounds_to_window,
+                place_obstacle.run_if(input_just_pressed(MouseButton::Left)),
+            ),
+        )
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    settings: Res<BoidSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn(Camera2d);
+
+    // 朝向 +x 的三角形
+    let mesh = meshes.add(Triangle2d::new(
+        Vec2::new(8.0, 0.0),
+        Vec2::new(-5.0, 4.0),
+        Vec2::new(-5.0, -4.0),
+    ));
+    let material = materials.add(Color::srgb(0.4, 0.8, 1.0));
+    let mut rng = ChaCha8Rng::seed_from_u64(19878367467713);
+    let bounds = settings.bounds;
+    for _ in 0..NUM_BOIDS {
+        let position = bounds.min + bounds.size() * Vec2::new(rng.random(), rng.random());
+        let direction = Vec2::from_angle(rng.random_range(0.0..std::f32::consts::TAU));
+        commands.spawn((
+            Mesh2d(mesh.clone()),
+            MeshMaterial2d(material.clone()),
+            Transform::from_translation(position.extend(0.0)),
+            Boid::new(direction * settings.max_speed),
+        ));
+    }
+
+    for position in [Vec2::new(-300.0, 100.0), Vec2::new(250.0, -150.0)] {
+        spawn_obstacle(&mut commands, &mut meshes, &mut materials, position);
+    }
+}
+
+fn spawn_obstacle(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    position: Vec2,
+) {
+    commands.spawn((
+        Mesh2d(meshes.add(Circle::new(OBSTACLE_RADIUS))),
+        MeshMaterial2d(materials.add(Color::srgb(0.9, 0.3, 0.2))),
+        Transform::from_translation(position.extend(-1.0)),
+        Obstacle {
+            radius: OBSTACLE_RADIUS,
+        },
+    ));
+}
+
+// 活动范围跟随窗口大小
+fn fit_bounds_to_window(window: Single<&Window>, mut settings: ResMut<BoidSettings>) {
+    let rect = Rect::from_center_size(Vec2::ZERO, window.size());
+    if settings.bounds != rect {
+        settings.bounds = rect;
+    }
+}
+
+fn place_obstacle(
+    mut commands: Commands,
+    window: Single<&Window>,
+    camera: Single<(&Camera, &GlobalTransform)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let (camera, camera_transform) = *camera;
+    let Some(position) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor).ok())
+    else {
+        return;
+    };
+    spawn_obstacle(&mut commands, &mut meshes, &mut materials, position);
+}
@@ -1,72 +1,465 @@
 //! 使用 parallelIterator 进行并行迭代器查询
 //! 并行迭代器的优势是在于大量的物理性多线程运算
+//! 边界 Bounds 资源决定精灵越界时的行为, M 键切换 反射 / 环绕 / 吸收 / 减速反弹
+//! Bounds 不依赖 Window, 有窗口时跟随窗口大小, 无头运行时使用默认大小
+//! 碰撞 均匀网格粗筛 + 圆与圆的弹性碰撞, 每个精灵只写自己, 并行也不会有数据竞争, 相同种子结果相同
+//! 人口控制 根据帧时间增减精灵数量, 减少时禁用最早生成的精灵放回池中, 增加时优先从池中取出, P 键开关
 
-use bevy::{ecs::batching::BatchingStrategy, prelude::*};
+use bevy::{
+    diagnostic::{
+        Diagnostic, DiagnosticPath, Diagnostics, LogDiagnosticsPlugin, RegisterDiagnostic,
+    },
+    ecs::{entity_disabling::Disabled, system::SystemParam},
+    input::common_conditions::input_just_pressed,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    tasks::{ComputeTaskPool, ParallelSlice, TaskPool},
+    window::PresentMode,
+};
 use bevy_inspector_egui::{bevy_egui::EguiPlugin, quick::WorldInspectorPlugin};
+use blibli_bevy2::{
+    bounds::Bounds,
+    sprite_motion::{MotionBatching, Velocity, bounce_system, move_system},
+};
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 
-// 速度
-#[derive(Component, Deref)]
-struct Velocity(Vec2);
+// 查找碰撞对象时每个任务处理的精灵数
+const COLLISION_CHUNK_SIZE: usize = 64;
+
+// 碰撞半径, icon.png 256 像素缩放 0.1 后约 25 像素, 图标四周有透明边
+const SPRITE_RADIUS: f32 = 10.0;
+
+// 圆形碰撞体, 质量与面积成正比
+#[derive(Component)]
+struct Collider {
+    radius: f32,
+}
+
+// 碰撞前所有精灵状态的快照
+#[derive(Clone, Copy)]
+struct Body {
+    entity: Entity,
+    position: Vec2,
+    velocity: Vec2,
+    radius: f32,
+}
+
+impl Body {
+    // 与 other 重叠时返回 (指向 other 的法线, 重叠深度, other 的质量占比)
+    // 完全重合时没有方向, 不算重叠
+    fn contact(&self, other: &Body) -> Option<(Vec2, f32, f32)> {
+        let delta = other.position - self.position;
+        let distance = delta.length();
+        let overlap = self.radius + other.radius - distance;
+        if self.entity == other.entity || overlap <= 0.0 || distance <= 0.0 {
+            return None;
+        }
+        let mass = self.radius * self.radius;
+        let other_mass = other.radius * other.radius;
+        Some((delta / distance, overlap, other_mass / (mass + other_mass)))
+    }
+
+    // 重叠最深且正在靠近的精灵, 深度相同时取遍历顺序靠前的
+    fn partner(&self, bodies: &[Body], grid: &Grid) -> Option<usize> {
+        let mut best = None;
+        let mut deepest = 0.0;
+        for j in grid.neighbors(self.position) {
+            let other = &bodies[j];
+            let Some((normal, overlap, _)) = self.contact(other) else {
+                continue;
+            };
+            if (self.velocity - other.velocity).dot(normal) > 0.0 && overlap > deepest {
+                best = Some(j);
+                deepest = overlap;
+            }
+        }
+        best
+    }
+}
+
+// 均匀网格, 格子边长不小于最大直径时, 相交的圆只可能在相邻的 3×3 格子中
+struct Grid {
+    cell_size: f32,
+    // 格子里按快照下标升序存放
+    cells: HashMap<IVec2, Vec<usize>>,
+}
+
+impl Grid {
+    fn build(bodies: &[Body], cell_size: f32) -> Self {
+        let mut grid = Self {
+            cell_size,
+            cells: HashMap::new(),
+        };
+        for (i, body) in bodies.iter().enumerate() {
+            let cell = grid.cell(body.position);
+            grid.cells.entry(cell).or_default().push(i);
+        }
+        grid
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    // 固定的遍历顺序: 先 y 后 x, 格子内按下标
+    fn neighbors(&self, position: Vec2) -> impl Iterator<Item = usize> + '_ {
+        let center = self.cell(position);
+        (-1..=1)
+            .flat_map(move |y| (-1..=1).map(move |x| center + IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+// 当前启用的精灵数量
+const POPULATION: DiagnosticPath = DiagnosticPath::const_new("ch10/population");
+// 平滑后的帧时间 (毫秒)
+const FRAME_TIME: DiagnosticPath = DiagnosticPath::const_new("ch10/frame_time");
+
+// 生成顺序, 越小越早
+#[derive(Component)]
+struct SpawnOrder(u64);
+
+// 初始的精灵和人口控制器补充的精灵都从这里生成, 使用同一个随机数序列
+#[derive(Resource)]
+struct SpriteSpawner {
+    texture: Handle<Image>,
+    rng: ChaCha8Rng,
+    next_order: u64,
+}
+
+impl SpriteSpawner {
+    // 边界内随机的位置和速度
+    fn state(&mut self, bounds: &Bounds) -> (Transform, Velocity, SpawnOrder) {
+        // 获取一个随机向量
+        let v = 20.0
+            * Vec2::new(
+                self.rng.random::<f32>() - 0.5,
+                self.rng.random::<f32>() - 0.5,
+            );
+        let position = bounds.rect.min
+            + bounds.rect.size() * Vec2::new(self.rng.random::<f32>(), self.rng.random::<f32>());
+        let order = SpawnOrder(self.next_order);
+        self.next_order += 1;
+        (
+            Transform::from_translation(position.extend(0.0)).with_scale(Vec3::splat(0.1)),
+            Velocity(v),
+            order,
+        )
+    }
+
+    fn spawn(&mut self, commands: &mut Commands, bounds: &Bounds) {
+        let state = self.state(bounds);
+        commands.spawn((
+            Sprite::from_image(self.texture.clone()),
+            state,
+            Collider {
+                radius: SPRITE_RADIUS,
+            },
+        ));
+    }
+
+    // 从池中取出被禁用的精灵, 像新生成的一样重新随机
+    fn revive(&mut self, commands: &mut Commands, entity: Entity, bounds: &Bounds) {
+        let state = self.state(bounds);
+        commands.entity(entity).insert(state).remove::<Disabled>();
+    }
+}
+
+// 根据帧时间调整精灵数量
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+struct PopulationController {
+    enabled: bool,
+    // 目标帧时间 (毫秒)
+    target_ms: f32,
+    // 滞回区间, 平均帧时间低于 target·(1 - hysteresis) 才增加, 高于 target·(1 + hysteresis) 才减少
+    hysteresis: f32,
+    // 每次增减当前数量的比例
+    step: f32,
+    min: usize,
+    max: usize,
+    // 调整后至少等待的秒数, 让平均帧时间反映新的数量
+    cooldown: f32,
+    // 帧时间指数平滑的系数, 越小越平滑
+    smoothing: f32,
+    average_ms: f32,
+    since_change: f32,
+}
+
+impl Default for PopulationController {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target_ms: 1000.0 / 60.0,
+            hysteresis: 0.15,
+            step: 0.1,
+            min: 100,
+            max: 50_000,
+            cooldown: 0.5,
+            smoothing: 0.05,
+            average_ms: 0.0,
+            since_change: 0.0,
+        }
+    }
+}
+
+impl PopulationController {
+    // 记录一帧的耗时, 返回希望的精灵数量, 不需要调整时返回 None
+    fn update(&mut self, frame_ms: f32, dt: f32, population: usize) -> Option<usize> {
+        self.average_ms = if self.average_ms > 0.0 {
+            self.average_ms
+                .lerp(frame_ms, self.smoothing.clamp(0.0, 1.0))
+        } else {
+            frame_ms
+        };
+        self.since_change += dt;
+        if !self.enabled || self.since_change < self.cooldown {
+            return None;
+        }
+
+        let current = population as f32;
+        let wanted = if self.average_ms < self.target_ms * (1.0 - self.hysteresis) {
+            (current * (1.0 + self.step)).ceil() as usize
+        } else if self.average_ms > self.target_ms * (1.0 + self.hysteresis) {
+            (current * (1.0 - self.step)).floor() as usize
+        } else {
+            population
+        }
+        .clamp(self.min, self.max.max(self.min));
+        if wanted == population {
+            return None;
+        }
+        self.since_change = 0.0;
+        Some(wanted)
+    }
+}
+
+struct PopulationPlugin;
+
+impl Plugin for PopulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PopulationController>()
+            .register_type::<PopulationController>()
+            .register_diagnostic(Diagnostic::new(POPULATION))
+            .register_diagnostic(Diagnostic::new(FRAME_TIME).with_suffix("ms"))
+            .add_systems(
+                Update,
+                (
+                    toggle_population_control.run_if(input_just_pressed(KeyCode::KeyP)),
+                    control_population.run_if(resource_exists::<SpriteSpawner>),
+                )
+                    .chain(),
+            );
+    }
+}
 
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins)
+        // 垂直同步会把帧时间固定在刷新间隔上, 人口控制器需要看到真实的耗时
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                present_mode: PresentMode::AutoNoVsync,
+                ..default()
+            }),
+            ..default()
+        }))
         .add_plugins(EguiPlugin::default())
         .add_plugins(WorldInspectorPlugin::new())
+        .add_plugins(LogDiagnosticsPlugin::filtered(HashSet::from_iter([
+            POPULATION, FRAME_TIME,
+        ])))
+        .add_plugins(PopulationPlugin)
+        .init_resource::<Bounds>()
+        .register_type::<Bounds>()
+        .init_resource::<MotionBatching>()
         .add_systems(Startup, spawn_system)
-        .add_systems(Update, (move_system, bounce_system))
+        .add_systems(
+            Update,
+            (
+                fit_bounds_to_window.run_if(any_with_component::<Window>),
+                cycle_boundary_mode.run_if(input_just_pressed(KeyCode::KeyM)),
+                move_system,
+                collision_system,
+                bounce_system,
+            )
+                .chain(),
+        )
         .run();
 }
 
-fn spawn_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+// 精灵之间会碰撞, 随机分布在边界内, 而不是都从原点出发
+fn spawn_system(mut commands: Commands, asset_server: Res<AssetServer>, bounds: Res<Bounds>) {
     commands.spawn(Camera2d);
 
-    let texture = asset_server.load("icon.png");
-    let mut rng = ChaCha8Rng::seed_from_u64(19878367467713);
+    let mut spawner = SpriteSpawner {
+        texture: asset_server.load("icon.png"),
+        rng: ChaCha8Rng::seed_from_u64(19878367467713),
+        next_order: 0,
+    };
     for _ in 0..1280 {
-        // 获取一个随机向量
-        let v = 20.0 * Vec2::new(rng.random::<f32>() - 0.5, rng.random::<f32>() - 0.5);
-        commands.spawn((
-            Sprite::from_image(texture.clone()),
-            Transform::from_scale(Vec3::splat(0.1)),
-            Velocity(v),
-        ));
+        spawner.spawn(&mut commands, &bounds);
     }
-}
-// 移动
-fn move_system(mut query: Query<(&mut Transform, &Velocity), With<Sprite>>) {
-    query.par_iter_mut().for_each(|(mut transform, velocity)| {
-        transform.translation += velocity.extend(0.0);
-    });
+    commands.insert_resource(spawner);
 }
 
-// 反弹
-// 当 entity 超出边界时,反弹回来
-// bounce
-fn bounce_system(
-    window: Single<&Window>,
-    mut sprites: Query<(&Transform, &mut Velocity), With<Sprite>>,
+// 碰撞
+// 1. 复制所有精灵的状态作为快照, 建立网格
+// 2. 并行地为每个精灵找出重叠最深、正在靠近的那个精灵
+// 3. par_iter_mut 中每个精灵只根据快照修改自己, 互为最佳对象的两个精灵做一次弹性碰撞
+// 一帧内每个精灵最多参与一次碰撞, 同时撞上多个时把多份冲量叠加会凭空增加能量, 剩下的留到之后几帧
+// 两边的计算完全对称, 动量和动能守恒; 邻居按固定顺序遍历, 结果与线程数无关
+fn collision_system(
+    mut sprites: Query<(Entity, &mut Transform, &mut Velocity, &Collider), With<Sprite>>,
 ) {
-    let width = window.width();
-    let height = window.height();
-
-    let left = width / -2.;
-    let right = width / 2.;
-    let top = height / 2.;
-    let button = height / -2.;
+    let bodies: Vec<Body> = sprites
+        .iter()
+        .map(|(entity, transform, velocity, collider)| Body {
+            entity,
+            position: transform.translation.truncate(),
+            velocity: velocity.0,
+            radius: collider.radius,
+        })
+        .collect();
+    let max_radius = bodies.iter().map(|body| body.radius).fold(0.0, f32::max);
+    if max_radius <= 0.0 {
+        return;
+    }
+    let grid = Grid::build(&bodies, 2.0 * max_radius);
+    let indices: HashMap<Entity, usize> = bodies
+        .iter()
+        .enumerate()
+        .map(|(i, body)| (body.entity, i))
+        .collect();
+    let partners: Vec<Option<usize>> = bodies
+        .par_chunk_map(
+            ComputeTaskPool::get_or_init(TaskPool::default),
+            COLLISION_CHUNK_SIZE,
+            |_, chunk| {
+                chunk
+                    .iter()
+                    .map(|body| body.partner(&bodies, &grid))
+                    .collect::<Vec<_>>()
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect();
 
     sprites
         .par_iter_mut()
-        .batching_strategy(BatchingStrategy::fixed(32)) // 更改任务的颗粒度 N个任务合并执行
-        .for_each(|(transform, mut velocity)| {
-            if !(transform.translation.x > left
-                && transform.translation.x < right
-                && transform.translation.y < top
-                && transform.translation.y > button)
-            {
-                velocity.0 = -velocity.0;
+        .for_each(|(entity, mut transform, mut velocity, _)| {
+            let index = indices[&entity];
+            let body = &bodies[index];
+            // 与所有重叠的精灵按质量比例各自退开, 两边合起来正好消除重叠
+            let push: Vec2 = grid
+                .neighbors(body.position)
+                .filter_map(|j| body.contact(&bodies[j]))
+                .map(|(normal, overlap, share)| -normal * (overlap * share))
+                .sum();
+            if push != Vec2::ZERO {
+                transform.translation += push.extend(0.0);
+            }
+
+            let Some(partner) = partners[index] else {
+                return;
+            };
+            if partners[partner] != Some(index) {
+                return;
+            }
+            let other = &bodies[partner];
+            if let Some((normal, _, share)) = body.contact(other) {
+                let approaching = (body.velocity - other.velocity).dot(normal);
+                velocity.0 -= normal * (2.0 * share * approaching);
             }
         });
 }
+
+// 有窗口时边界跟随窗口大小
+fn fit_bounds_to_window(window: Single<&Window>, mut bounds: ResMut<Bounds>) {
+    let rect = Rect::from_center_size(Vec2::ZERO, window.size());
+    if bounds.rect != rect {
+        bounds.rect = rect;
+    }
+}
+
+fn toggle_population_control(mut controller: ResMut<PopulationController>) {
+    controller.enabled = !controller.enabled;
+    info!("population control: {}", controller.enabled);
+}
+
+// 启用的精灵和池中被禁用的精灵
+// 查询默认不包含带 Disabled 的实体, active 只有启用的精灵, pool 需要显式 With<Disabled>
+#[derive(SystemParam)]
+struct SpritePool<'w, 's> {
+    active: Query<'w, 's, (Entity, &'static SpawnOrder)>,
+    pool: Query<'w, 's, Entity, (With<SpawnOrder>, With<Disabled>)>,
+}
+
+impl SpritePool<'_, '_> {
+    fn population(&self) -> usize {
+        self.active.iter().len()
+    }
+
+    // 增加时优先从池中取出, 减少时禁用最早生成的精灵
+    fn resize(
+        &self,
+        wanted: usize,
+        commands: &mut Commands,
+        spawner: &mut SpriteSpawner,
+        bounds: &Bounds,
+    ) {
+        let population = self.population();
+        if wanted > population {
+            let mut pool = self.pool.iter();
+            for _ in population..wanted {
+                match pool.next() {
+                    Some(entity) => spawner.revive(commands, entity, bounds),
+                    None => spawner.spawn(commands, bounds),
+                }
+            }
+        } else {
+            let mut oldest: Vec<(u64, Entity)> = self
+                .active
+                .iter()
+                .map(|(entity, order)| (order.0, entity))
+                .collect();
+            oldest.sort_unstable();
+            for &(_, entity) in &oldest[..population - wanted] {
+                commands.entity(entity).insert(Disabled);
+            }
+        }
+    }
+}
+
+// 使用真实时间测量帧时间, 不受 Time<Virtual> 暂停和缩放的影响
+fn control_population(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    bounds: Res<Bounds>,
+    mut controller: ResMut<PopulationController>,
+    mut spawner: ResMut<SpriteSpawner>,
+    mut diagnostics: Diagnostics,
+    sprites: SpritePool,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+    let population = sprites.population();
+    let wanted = controller.update(dt * 1000.0, dt, population);
+    let average_ms = controller.average_ms;
+    diagnostics.add_measurement(&POPULATION, || population as f64);
+    diagnostics.add_measurement(&FRAME_TIME, || average_ms as f64);
+
+    if let Some(wanted) = wanted {
+        sprites.resize(wanted, &mut commands, &mut spawner, &bounds);
+    }
+}
+
+fn cycle_boundary_mode(mut bounds: ResMut<Bounds>) {
+    bounds.mode = bounds.mode.next();
+    info!("boundary: {:?}", bounds.mode);
+}
@@ -1,17 +1,50 @@
 //! hierarchy 层次结构
+//! 每个实体都有 Name, 用 HierarchyPath 按 "root/blue" 这样的路径查找子节点, 而不是 Children 中的下标
+//! 启动时把整棵树保存到 assets/scenes/ch11_hierarchy.ron, 按 L 键从文件加载一份副本
+//! lime 带有 DespawnPolicy, 4 秒时随 root 的子节点一起删除, 它的子节点 tip 按策略保留下来, 按 P 键切换策略
+//! 删除时间写在 Timeline 中, 空格暂停游戏时间时时间线也会暂停, 按 C 键取消每秒打印层次结构的循环
+//! 旋转由 TransformAnimation 关键帧片段驱动, lime 先播放一遍弹出动画, 结束后开始旋转
 
-use std::f32::consts::PI;
+use std::{f32::consts::PI, time::Duration};
 
 use bevy::{
+    asset::io::file::FileAssetReader,
     color::palettes::css::{BLUE, LIME},
+    input::common_conditions::input_just_pressed,
     prelude::*,
 };
+use blibli_bevy2::{
+    despawn_policy::{DespawnPolicy, DespawnPolicyPlugin},
+    hierarchy_path::HierarchyPath,
+    hierarchy_scene::HierarchyScene,
+    timeline::{Timeline, TimelineId, TimelinePlugin},
+    transform_animation::{
+        ClipEnded, ClipMode, TransformAnimation, TransformAnimationPlugin, TransformClip,
+    },
+};
+
+const SCENE_PATH: &str = "assets/scenes/ch11_hierarchy.ron";
 
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins)
-        .add_systems(Startup, setup)
-        .add_systems(Update, rotate)
+        .add_plugins((
+            DefaultPlugins,
+            DespawnPolicyPlugin,
+            TimelinePlugin,
+            TransformAnimationPlugin,
+        ))
+        .add_systems(Startup, (setup, schedule_timeline))
+        .add_systems(PostStartup, (print_paths, save_hierarchy))
+        .add_observer(spin_after_pop)
+        .add_systems(
+            Update,
+            (
+                load_hierarchy.run_if(input_just_pressed(KeyCode::KeyL)),
+                cycle_policy.run_if(input_just_pressed(KeyCode::KeyP)),
+                toggle_pause.run_if(input_just_pressed(KeyCode::Space)),
+                cancel_tree_log.run_if(input_just_pressed(KeyCode::KeyC)),
+            ),
+        )
         .run();
 }
 
@@ -21,65 +54,191 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     // 创建一个根节点获取 Entity
     let parent = commands
         .spawn((
+            Name::new("root"),
             Sprite::from_image(textrue.clone()),
-            Transform::from_scale(Vec3::splat(0.75)),
+            // 父节点旋转会带动整个进行旋转
+            TransformAnimation::new(spin(Transform::from_scale(Vec3::splat(0.75)), -4.0)),
         ))
         .with_children(|parent| {
             parent.spawn((
+                Name::new("blue"),
                 Sprite {
                     image: textrue.clone(),
                     color: BLUE.into(),
                     ..default()
                 },
                 // 基于父节点进行偏移和形变 ,缩放有继承关系
-                Transform::from_xyz(250., 0., 0.).with_scale(Vec3::splat(0.75)),
+                TransformAnimation::new(spin(
+                    Transform::from_xyz(250., 0., 0.).with_scale(Vec3::splat(0.75)),
+                    2.0,
+                )),
             ));
         })
         .id();
 
     let child = commands
         .spawn((
+            Name::new("lime"),
+            Sprite {
+                image: textrue.clone(),
+                color: LIME.into(),
+                ..default()
+            },
+            TransformAnimation::new(pop(
+                Transform::from_xyz(0.0, 250.0, 0.0).with_scale(Vec3::splat(0.75))
+            )),
+            // 删除 lime 时 tip 挂到 root 上, 而不是一起删除
+            DespawnPolicy::Reparent,
+        ))
+        .with_child((
+            Name::new("tip"),
             Sprite {
                 image: textrue.clone(),
                 color: LIME.into(),
                 ..default()
             },
-            Transform::from_xyz(0.0, 250.0, 0.0).with_scale(Vec3::splat(0.75)),
+            Transform::from_xyz(0.0, 200.0, 0.0).with_scale(Vec3::splat(0.5)),
         ))
         .id();
 
     commands.entity(parent).add_child(child);
 }
 
-fn rotate(
-    mut transform_query: Query<&mut Transform, With<Sprite>>,
-    //  despawn_children 删除后 Children 不会存在,无法查询
-    mut parent_query: Query<(Entity, Option<&Children>), With<Sprite>>,
-    mut commands: Commands,
-    time: Res<Time>,
-) {
-    for (parent, children) in &mut parent_query {
-        if let Ok(mut transform) = transform_query.get_mut(parent) {
-            // 父节点旋转会带动整个进行旋转
-            transform.rotate_z(-PI / 2.0 * time.delta_secs());
+// 路径在 Startup 的命令应用之后才能查到
+fn print_paths(hierarchy: HierarchyPath) {
+    match hierarchy.resolve_all("root/*") {
+        Ok(children) => {
+            for child in children {
+                info!("found {}", hierarchy.path_of(child).unwrap_or_default());
+            }
         }
+        Err(err) => warn!("{err}"),
+    }
+    // 拼错的路径会说明在哪一层找不到, 以及这一层有哪些名字
+    if let Err(err) = hierarchy.resolve("root/bleu") {
+        info!("expected error: {err}");
+    }
+}
+
+// 在删除子节点之前保存
+fn save_hierarchy(world: &mut World) -> Result {
+    let root = world
+        .query::<(Entity, &Name)>()
+        .iter(world)
+        .find(|(_, name)| name.as_str() == "root")
+        .map(|(entity, _)| entity)
+        .ok_or("no entity named root")?;
+    let scene = HierarchyScene::capture(world, root)?;
+    let path = FileAssetReader::get_base_path().join(SCENE_PATH);
+    scene.save(&path)?;
+    info!(
+        "saved {} entities to {}",
+        scene.entities.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+// 副本的根节点改名为 copy 并向左平移, 与原来的 root 区分开
+fn load_hierarchy(mut commands: Commands, asset_server: Res<AssetServer>) -> Result {
+    let mut scene = HierarchyScene::load(FileAssetReader::get_base_path().join(SCENE_PATH))?;
+    if let Some(root) = scene.entities.first_mut() {
+        root.name = Some("copy".to_string());
+        root.translation.x -= 400.0;
+    }
+    let root = scene.spawn(&mut commands, &asset_server)?;
+    info!("loaded {} entities, root {root}", scene.entities.len());
+    Ok(())
+}
 
-        let Some(children) = children else {
-            return;
-        };
-        for child in children {
-            if let Ok(mut transform) = transform_query.get_mut(*child) {
-                transform.rotate_z(PI * time.delta_secs());
+// Cascade -> Reparent -> Detach, 只在 lime 删除之前有效
+fn cycle_policy(hierarchy: HierarchyPath, mut policies: Query<&mut DespawnPolicy>) {
+    let Ok(lime) = hierarchy.resolve("root/lime") else {
+        return;
+    };
+    if let Ok(mut policy) = policies.get_mut(lime) {
+        *policy = policy.next();
+        info!("lime despawn policy: {:?}", *policy);
+    }
+}
+
+// 每秒打印层次结构的循环条目
+#[derive(Resource)]
+struct TreeLog(TimelineId);
+
+fn schedule_timeline(mut commands: Commands, mut timeline: ResMut<Timeline>) {
+    // 按名字删除蓝色的子节点, 不依赖它在 Children 中的位置
+    timeline.at(
+        Duration::from_secs(2),
+        |hierarchy: HierarchyPath, mut commands: Commands| match hierarchy.resolve("root/blue") {
+            Ok(blue) => commands.entity(blue).despawn(),
+            Err(err) => warn!("{err}"),
+        },
+    );
+    // lime 的 tip 按 DespawnPolicy 保留或随之删除
+    timeline.at(
+        Duration::from_secs(4),
+        |hierarchy: HierarchyPath, mut commands: Commands| match hierarchy.resolve("root") {
+            Ok(root) => {
+                commands.entity(root).despawn_children();
             }
-        }
+            Err(err) => warn!("{err}"),
+        },
+    );
+    let id = timeline.every(Duration::from_secs(1), None, log_tree);
+    commands.insert_resource(TreeLog(id));
+}
 
-        if time.elapsed_secs() >= 2.0 && children.len() == 2 {
-            let child = children.first().unwrap();
-            commands.entity(*child).despawn();
-        }
+fn log_tree(hierarchy: HierarchyPath, timeline: Res<Timeline>) {
+    if let Ok(entities) = hierarchy.resolve_all("**") {
+        let paths: Vec<String> = entities
+            .into_iter()
+            .filter_map(|entity| hierarchy.path_of(entity).ok())
+            .collect();
+        info!("{:.1}s: {}", timeline.now().as_secs_f32(), paths.join(", "));
+    }
+}
 
-        if time.elapsed_secs() >= 4.0 {
-            commands.entity(parent).despawn_children();
-        }
+fn cancel_tree_log(tree_log: Res<TreeLog>, mut timeline: ResMut<Timeline>) {
+    if timeline.cancel(tree_log.0) {
+        info!("tree log cancelled");
+    }
+}
+
+fn toggle_pause(mut time: ResMut<Time<Virtual>>) {
+    if time.is_paused() {
+        time.unpause();
+    } else {
+        time.pause();
+    }
+}
+
+// 绕自身 z 轴旋转一圈用 seconds 秒, 负数为顺时针
+// 相邻关键帧相差 90°, slerp 总是走较短的一侧, 不能只用首尾两帧
+fn spin(base: Transform, seconds: f32) -> TransformClip {
+    (0..=4).fold(TransformClip::new(ClipMode::Loop), |clip, i| {
+        let quarter = i as f32 / 4.0;
+        clip.keyframe(
+            seconds.abs() * quarter,
+            base.with_rotation(Quat::from_rotation_z(2.0 * PI * quarter * seconds.signum())),
+            EaseFunction::Linear,
+        )
+    })
+}
+
+// 从 0 放大到 base 的缩放, 稍微超出后回弹
+fn pop(base: Transform) -> TransformClip {
+    TransformClip::new(ClipMode::Once)
+        .keyframe(0.0, base.with_scale(Vec3::ZERO), EaseFunction::BackOut)
+        .keyframe(1.0, base, EaseFunction::Linear)
+}
+
+fn spin_after_pop(ended: On<ClipEnded>, mut animations: Query<&mut TransformAnimation>) {
+    let Ok(mut animation) = animations.get_mut(ended.entity) else {
+        return;
+    };
+    if animation.clip.mode == ClipMode::Once {
+        let base = animation.sample().unwrap_or_default();
+        *animation = TransformAnimation::new(spin(base, 2.0));
     }
 }
@@ -9,15 +9,120 @@
 //! system pipe
 //! Query 用法 QueryData 与QueryFilter
 //! Query 的延伸用法 Single 和 Populated 和 Option<Single>
+//! 技能冷却 每个技能单独冷却,另有公共冷却(GCD),空格 暂停/恢复 虚拟时间
+//! 预制体(prefab) 实体模板写在 assets/prefabs/*.ron 中,通过反射创建组件
 
-use bevy::prelude::*;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{
+    asset::io::file::FileAssetReader,
+    ecs::reflect::ReflectComponent,
+    input::common_conditions::input_just_pressed,
+    platform::collections::HashMap,
+    prelude::*,
+    reflect::{TypeRegistry, serde::TypedReflectDeserializer},
+};
+use blibli_bevy2::entity_printer::EntityPrinter;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, Visitor};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 enum QueryError {
     #[error("none")]
     None,
+    #[error("{0:?} cooling down, {1:?} left")]
+    CoolingDown(Ability, Duration),
+}
+
+// 技能
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ability {
+    Bomb,
+    Lash,
+    Glare,
+}
+
+impl Ability {
+    const ALL: [Ability; 3] = [Ability::Bomb, Ability::Lash, Ability::Glare];
+
+    fn from_key(code: KeyCode) -> Option<Self> {
+        match code {
+            KeyCode::KeyB => Some(Ability::Bomb),
+            KeyCode::KeyL => Some(Ability::Lash),
+            KeyCode::KeyG => Some(Ability::Glare),
+            _ => None,
+        }
+    }
+
+    // 技能自身的冷却时长
+    fn cooldown(self) -> f32 {
+        match self {
+            Ability::Bomb => 5.0,
+            Ability::Lash => 1.0,
+            Ability::Glare => 3.0,
+        }
+    }
+}
+
+// 技能冷却
+// Timer 使用 TimerMode::Once, is_finished() 表示冷却完毕
+// 由 Time<Virtual> 驱动,所以暂停时冷却也会冻结
+#[derive(Resource, Debug)]
+struct Cooldowns {
+    abilities: [Timer; 3],
+    global: Timer,
 }
+
+impl Cooldowns {
+    fn new(global: f32) -> Self {
+        Self {
+            abilities: Ability::ALL.map(|ability| ready_timer(ability.cooldown())),
+            global: ready_timer(global),
+        }
+    }
+
+    fn tick(&mut self, delta: Duration) {
+        for timer in &mut self.abilities {
+            timer.tick(delta);
+        }
+        self.global.tick(delta);
+    }
+
+    // 剩余冷却时间, 取技能冷却与公共冷却中较长的一个
+    fn remaining(&self, ability: Ability) -> Duration {
+        self.abilities[ability as usize]
+            .remaining()
+            .max(self.global.remaining())
+    }
+
+    fn is_ready(&self, ability: Ability) -> bool {
+        self.remaining(ability).is_zero()
+    }
+
+    // 释放技能,重新开始该技能与公共冷却
+    fn trigger(&mut self, ability: Ability) {
+        self.abilities[ability as usize].reset();
+        self.global.reset();
+    }
+}
+
+impl Default for Cooldowns {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+// 创建一个已经走完的 Timer, 开局技能就可以直接使用
+fn ready_timer(secs: f32) -> Timer {
+    let mut timer = Timer::from_seconds(secs, TimerMode::Once);
+    timer.tick(timer.duration());
+    timer
+}
+
 #[derive(Debug, Resource)]
 struct NotifyPlayerTimer(Timer);
 
@@ -25,33 +130,251 @@ struct NotifyPlayerTimer(Timer);
 struct NotifyEnemiesTimer(Timer);
 
 // 玩家
-#[derive(Component)]
+// 需要在 prefab 中使用的组件都要 derive(Reflect) 并 #[reflect(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct Player;
 
 // boss
-#[derive(Component, Debug)]
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
 struct Boss;
 
 // 敌人
-#[derive(Component, Debug)]
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
 struct Enemy;
 
 // 生命值
-#[derive(Component, Deref, DerefMut, Debug)]
+#[derive(Component, Reflect, Deref, DerefMut, Debug)]
+#[reflect(Component)]
 struct Health(i64);
 
 // 护甲
-#[derive(Component, Debug)]
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
 struct Armor;
 
 // 颜色(是否隐身)
-#[derive(Component, Debug)]
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
 enum BodyColor {
     Red,
     Green,
     White,
 }
 
+#[derive(Debug, Error)]
+enum PrefabError {
+    #[error("failed to read prefab `{path}`: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("prefab `{name}` is invalid: {source}")]
+    Parse {
+        name: String,
+        source: ron::error::SpannedError,
+    },
+    #[error("prefab `{name}` inherits unknown prefab `{parent}`")]
+    UnknownParent { name: String, parent: String },
+    // 环上的模板名, 如 a -> b -> a
+    #[error("prefab inheritance forms a cycle: {0}")]
+    Cycle(String),
+    #[error("unknown prefab `{0}`")]
+    Unknown(String),
+}
+
+// 一个实体模板
+// inherits 先创建父模板的组件,再用自己的组件覆盖
+#[derive(Default)]
+struct Prefab {
+    inherits: Option<String>,
+    components: Vec<(ReflectComponent, Box<dyn PartialReflect>)>,
+}
+
+// 所有已加载的模板, key 为文件名(不含 .ron)
+#[derive(Resource, Default)]
+struct Prefabs(HashMap<String, Prefab>);
+
+impl Prefabs {
+    fn load_dir(dir: &Path, registry: &TypeRegistry) -> Result<Self, PrefabError> {
+        let io_error = |source| PrefabError::Io {
+            path: dir.to_path_buf(),
+            source,
+        };
+        let mut prefabs = Prefabs::default();
+        for entry in std::fs::read_dir(dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.extension().is_none_or(|ext| ext != "ron") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let text = std::fs::read_to_string(&path).map_err(|source| PrefabError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            let prefab = ron::Options::default()
+                .from_str_seed(&text, PrefabDeserializer { registry })
+                .map_err(|source| PrefabError::Parse {
+                    name: name.to_string(),
+                    source,
+                })?;
+            prefabs.0.insert(name.to_string(), prefab);
+        }
+
+        // 加载时就检查继承关系,避免到创建实体时才出错
+        for name in prefabs.0.keys() {
+            prefabs.chain(name)?;
+        }
+        Ok(prefabs)
+    }
+
+    // 从最顶层的父模板到自身
+    fn chain(&self, name: &str) -> Result<Vec<&Prefab>, PrefabError> {
+        let mut chain = Vec::new();
+        let mut names = Vec::new();
+        let mut current = name;
+        loop {
+            if let Some(start) = names.iter().position(|visited| *visited == current) {
+                let mut cycle = names[start..].to_vec();
+                cycle.push(current);
+                return Err(PrefabError::Cycle(cycle.join(" -> ")));
+            }
+            let prefab = self.0.get(current).ok_or_else(|| match chain.last() {
+                None => PrefabError::Unknown(current.to_string()),
+                Some(_) => PrefabError::UnknownParent {
+                    name: name.to_string(),
+                    parent: current.to_string(),
+                },
+            })?;
+            names.push(current);
+            chain.push(prefab);
+            match &prefab.inherits {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+}
+
+// 解析 ( inherits: Some("..."), components: { "Health": (30), ... } )
+struct PrefabDeserializer<'a> {
+    registry: &'a TypeRegistry,
+}
+
+impl<'de> DeserializeSeed<'de> for PrefabDeserializer<'_> {
+    type Value = Prefab;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Prefab, D::Error> {
+        deserializer.deserialize_struct("Prefab", &["inherits", "components"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for PrefabDeserializer<'_> {
+    type Value = Prefab;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a prefab")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Prefab, A::Error> {
+        let mut prefab = Prefab::default();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "inherits" => prefab.inherits = map.next_value()?,
+                "components" => {
+                    prefab.components = map.next_value_seed(ComponentsDeserializer {
+                        registry: self.registry,
+                    })?
+                }
+                _ => return Err(de::Error::unknown_field(&key, &["inherits", "components"])),
+            }
+        }
+        Ok(prefab)
+    }
+}
+
+// 组件名可以是完整路径,也可以是短名字
+// 组件的值交给 TypedReflectDeserializer, 字段写错时会报出具体的字段
+struct ComponentsDeserializer<'a> {
+    registry: &'a TypeRegistry,
+}
+
+impl<'de> DeserializeSeed<'de> for ComponentsDeserializer<'_> {
+    type Value = Vec<(ReflectComponent, Box<dyn PartialReflect>)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ComponentsDeserializer<'_> {
+    type Value = Vec<(ReflectComponent, Box<dyn PartialReflect>)>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of component name to value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut components = Vec::new();
+        while let Some(name) = map.next_key::<String>()? {
+            let registration = self
+                .registry
+                .get_with_type_path(&name)
+                .or_else(|| self.registry.get_with_short_type_path(&name))
+                .ok_or_else(|| de::Error::custom(format!("unknown component `{name}`")))?;
+            let reflect_component = registration.data::<ReflectComponent>().ok_or_else(|| {
+                de::Error::custom(format!(
+                    "`{name}` is not a component (missing #[reflect(Component)])"
+                ))
+            })?;
+            let value =
+                map.next_value_seed(TypedReflectDeserializer::new(registration, self.registry))?;
+            components.push((reflect_component.clone(), value));
+        }
+        Ok(components)
+    }
+}
+
+trait SpawnPrefabExt {
+    fn spawn_prefab(&mut self, name: &str) -> EntityCommands<'_>;
+}
+
+impl SpawnPrefabExt for Commands<'_, '_> {
+    // 按模板创建实体,模板不存在时返回错误交给 bevy 处理
+    // 先解析继承链,失败时删除预留的实体,不会留下空实体
+    fn spawn_prefab(&mut self, name: &str) -> EntityCommands<'_> {
+        let name = name.to_string();
+        let id = self.spawn_empty().id();
+        self.queue(move |world: &mut World| -> Result<(), PrefabError> {
+            let registry = world.resource::<AppTypeRegistry>().clone();
+            let registry = registry.read();
+            world.resource_scope(|world, prefabs: Mut<Prefabs>| {
+                let chain = match prefabs.chain(&name) {
+                    Ok(chain) => chain,
+                    Err(err) => {
+                        world.despawn(id);
+                        return Err(err);
+                    }
+                };
+                let mut entity = world.entity_mut(id);
+                for prefab in chain {
+                    for (reflect_component, component) in &prefab.components {
+                        reflect_component.insert(&mut entity, component.as_ref(), &registry);
+                    }
+                }
+                Ok(())
+            })
+        });
+        self.entity(id)
+    }
+}
+
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
@@ -63,25 +386,50 @@ fn main() {
             5.0,
             TimerMode::Repeating,
         )))
-        .add_systems(Startup, setup)
+        .init_resource::<Cooldowns>()
+        .register_type::<Player>()
+        .register_type::<Boss>()
+        .register_type::<Enemy>()
+        .register_type::<Health>()
+        .register_type::<Armor>()
+        .register_type::<BodyColor>()
+        .add_systems(Startup, (load_prefabs, setup).chain())
         .add_systems(
             Update,
-            choice
-                .pipe(bomb_all)
-                .pipe(lash_enemy)
-                .pipe(glare_all)
-                .map(in_choice_map),
+            toggle_pause.run_if(input_just_pressed(KeyCode::Space)),
+        )
+        // 两者都修改 Cooldowns, 先推进冷却再检查, 本帧结束的冷却本帧就能使用
+        .add_systems(
+            Update,
+            (
+                tick_cooldowns,
+                choice
+                    .pipe(check_cooldown)
+                    .pipe(bomb_all)
+                    .pipe(lash_enemy)
+                    .pipe(glare_all)
+                    .map(in_choice_map),
+            )
+                .chain(),
         )
         .add_systems(Update, refresh_all)
         .add_systems(Update, (notify_player, notify_enemies).chain())
         .run();
 }
 
+// 读取 assets/prefabs 下的所有模板
+fn load_prefabs(mut commands: Commands, registry: Res<AppTypeRegistry>) -> Result {
+    let dir = FileAssetReader::get_base_path().join("assets/prefabs");
+    let prefabs = Prefabs::load_dir(&dir, &registry.read())?;
+    commands.insert_resource(prefabs);
+    Ok(())
+}
+
+// 实体的组件都在模板文件中
 fn setup(mut commands: Commands) {
-    commands.spawn((Player, Health(100), Armor));
-    commands.spawn((Enemy, Health(30), BodyColor::Green));
-    commands.spawn((Enemy, Health(1)));
-    commands.spawn((Boss, BodyColor::Red, Armor, Health(100)));
+    for name in ["player", "green_enemy", "enemy", "boss"] {
+        commands.spawn_prefab(name);
+    }
 }
 
 // 等待用户输入
@@ -91,6 +439,39 @@ fn choice(input: Res<ButtonInput<KeyCode>>) -> Result<KeyCode, QueryError> {
     };
     Ok(*code)
 }
+
+// 冷却中的技能不会向下传递
+fn check_cooldown(
+    In(key): In<Result<KeyCode, QueryError>>,
+    mut cooldowns: ResMut<Cooldowns>,
+) -> Result<KeyCode, QueryError> {
+    let code = key?;
+    let Some(ability) = Ability::from_key(code) else {
+        return Ok(code);
+    };
+
+    if !cooldowns.is_ready(ability) {
+        let remaining = cooldowns.remaining(ability);
+        println!("{ability:?} cooling down: {:.1}s", remaining.as_secs_f32());
+        return Err(QueryError::CoolingDown(ability, remaining));
+    }
+
+    cooldowns.trigger(ability);
+    Ok(code)
+}
+
+// 使用虚拟时间推进冷却
+fn tick_cooldowns(time: Res<Time<Virtual>>, mut cooldowns: ResMut<Cooldowns>) {
+    cooldowns.tick(time.delta());
+}
+
+fn toggle_pause(mut time: ResMut<Time<Virtual>>) {
+    if time.is_paused() {
+        time.unpause();
+    } else {
+        time.pause();
+    }
+}
 // 丢炸弹, 场景中的所有生物都要扣血
 // 玩家和怪物受到伤害不同,需要分别处理(两个query)
 fn bomb_all(
@@ -183,6 +564,7 @@ fn notify_player(
     time: Res<Time>,
     mut timer: ResMut<NotifyPlayerTimer>,
     player: Option<Single<EntityRef, With<Player>>>, // 获取entity 的组件
+    cooldowns: Res<Cooldowns>,
 ) {
     timer.0.tick(time.delta());
     // 不足自定义的时间,就什么都不做
@@ -196,6 +578,13 @@ fn notify_player(
 
     println!("---------------------------");
     println!("(B)omb -60 (L)ash -10 (G)lare");
+    for ability in Ability::ALL {
+        print!(
+            "{ability:?}: {:.1}s  ",
+            cooldowns.remaining(ability).as_secs_f32()
+        );
+    }
+    println!();
 
     let Some(health) = player.get::<Health>() else {
         return;
@@ -212,41 +601,22 @@ fn notify_player(
         armor
     );
 }
+// EntityRef 可以读取实体上的所有组件, 交给 EntityPrinter 通过反射打印
 fn notify_enemies(
     time: Res<Time>,
     mut timer: ResMut<NotifyEnemiesTimer>,
-    enemies: Populated<
-        (
-            Entity,
-            &Health,
-            Option<&Armor>,
-            Option<&BodyColor>,
-            Option<&Boss>,
-            Option<&Enemy>,
-        ),
-        Without<Player>,
-    >,
+    enemies: Populated<EntityRef, (With<Health>, Without<Player>)>,
+    registry: Res<AppTypeRegistry>,
 ) {
     timer.0.tick(time.delta());
     if !timer.0.just_finished() {
         return;
     }
 
-    for (entity, health, armor, bg, boss, enemy) in enemies {
-        print!("entity:{}", entity);
-        print!(" ({})", **health);
-        print!(" / ({:?})", armor);
-        if let Some(bg) = bg {
-            print!(" / {bg:?}")
-        }
-
-        if boss.is_some() {
-            print!(" / boss")
-        }
-        if enemy.is_some() {
-            print!(" / enemy")
-        }
-        println!()
+    let registry = registry.read();
+    let printer = EntityPrinter::default();
+    for enemy in enemies {
+        printer.print_entity_ref(enemy, &registry);
     }
     println!()
 }
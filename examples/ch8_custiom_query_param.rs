@@ -6,67 +6,89 @@
 //！ - 您可以绕过查询元组中 15 个组件的限制。//!
 
 use bevy::{
-    ecs::query::{QueryData, QueryFilter},
+    ecs::{
+        component::Tick,
+        query::{QueryData, QueryFilter},
+        system::SystemChangeTick,
+    },
     prelude::*,
 };
+use blibli_bevy2::{entity_printer::EntityPrinter, explainable, query_explain::explain};
 use std::fmt::Debug;
 
-#[derive(Debug, Component)]
+// derive(Reflect) 后可以用 EntityPrinter 打印
+#[derive(Debug, Component, Reflect)]
+#[reflect(Component)]
 struct ComponentA;
-#[derive(Debug, Component)]
+#[derive(Debug, Component, Reflect)]
+#[reflect(Component)]
 struct ComponentB;
-#[derive(Debug, Component)]
+#[derive(Debug, Component, Reflect)]
+#[reflect(Component)]
 struct ComponentC;
-#[derive(Debug, Component)]
+#[derive(Debug, Component, Reflect)]
+#[reflect(Component)]
 struct ComponentD;
-#[derive(Debug, Component)]
+#[derive(Debug, Component, Reflect)]
+#[reflect(Component)]
 struct ComponentZ;
 
 // QueryData 的目的是集成查询结果到一个自定义的结构体
 // 所以如果Query 中的Entity 不包含(非Option集成)某个Component 时就不会有结果,该System 就不会被执行
-#[derive(QueryData)]
-#[query_data(derive(Debug))]
-struct ReadOnlyCustomQuery<T: Component + Debug, P: Component + Debug> {
-    entity: Entity,
-    a: &'static ComponentA,
-    b: Option<&'static ComponentB>,
-    nested: NestedQuery,
-    optional_nested: Option<NestedQuery>,
-    optional_tuple: Option<(&'static ComponentB, &'static ComponentZ)>,
-    z: Option<&'static ComponentZ>, // 因为z 没有加入
-    generic: GenericQuery<T, P>,
-    enpty: EmptyQuery,
+// explainable! 用同一份字段列表生成 ExplainTerm, 供 explain_custom_query 使用
+explainable! {
+    #[derive(QueryData)]
+    #[query_data(derive(Debug))]
+    struct ReadOnlyCustomQuery<T: Component + Debug, P: Component + Debug> {
+        entity: Entity,
+        a: &'static ComponentA,
+        b: Option<&'static ComponentB>,
+        nested: NestedQuery,
+        optional_nested: Option<NestedQuery>,
+        optional_tuple: Option<(&'static ComponentB, &'static ComponentZ)>,
+        z: Option<&'static ComponentZ>, // 因为z 没有加入
+        generic: GenericQuery<T, P>,
+        enpty: EmptyQuery,
+    }
 }
 
-#[derive(QueryData)]
-#[query_data(derive(Debug))]
-struct NestedQuery {
-    c: &'static ComponentC,
-    d: Option<&'static ComponentD>,
+explainable! {
+    #[derive(QueryData)]
+    #[query_data(derive(Debug))]
+    struct NestedQuery {
+        c: &'static ComponentC,
+        d: Option<&'static ComponentD>,
+    }
 }
 
-#[derive(QueryData)]
-#[query_data(derive(Debug))]
-struct GenericQuery<T: Component, P: Component> {
-    generic: (&'static T, &'static P),
+explainable! {
+    #[derive(QueryData)]
+    #[query_data(derive(Debug))]
+    struct GenericQuery<T: Component, P: Component> {
+        generic: (&'static T, &'static P),
+    }
 }
 
-#[derive(QueryData)]
-#[query_data(derive(Debug))]
-struct EmptyQuery {
-    empt: (),
+explainable! {
+    #[derive(QueryData)]
+    #[query_data(derive(Debug))]
+    struct EmptyQuery {
+        empt: (),
+    }
 }
 
 // 所有的结构属性都是and 联合,所以一旦有一个条件不满足,将没有结果
-#[derive(QueryFilter)]
-struct CustomQueryFilter<T: Component, P: Component> {
-    _c: With<ComponentC>,
-    _d: With<ComponentD>,
-    // 如果不将 Added 加入到Or 条件,那么query就会得到一次结果
-    _or: Or<(Added<ComponentC>, Changed<ComponentD>, Without<ComponentZ>)>,
-    _generic_tuple: (With<T>, With<P>),
-    // 满足不了CompoenntZ 条件
-    // _generic_tuple2:(With<T>,With<P>,With<ComponentZ>)
+explainable! {
+    #[derive(QueryFilter)]
+    struct CustomQueryFilter<T: Component, P: Component> {
+        _c: With<ComponentC>,
+        _d: With<ComponentD>,
+        // 如果不将 Added 加入到Or 条件,那么query就会得到一次结果
+        _or: Or<(Added<ComponentC>, Changed<ComponentD>, Without<ComponentZ>)>,
+        _generic_tuple: (With<T>, With<P>),
+        // 满足不了CompoenntZ 条件
+        // _generic_tuple2:(With<T>,With<P>,With<ComponentZ>)
+    }
 }
 
 #[derive(QueryData)]
@@ -82,11 +104,27 @@ struct CustomQuery<T: Component + Debug, P: Component + Debug> {
     empty: EmptyQuery,
 }
 
+// print_components_read_only 上一次运行时的 tick
+// explain 用它判断 Added / Changed, 结果与那次查询一致
+#[derive(Resource, Default)]
+struct ReadOnlyQueryTicks {
+    last_run: Tick,
+    this_run: Tick,
+}
+
 type NestedTupleQuery<'w> = (&'w ComponentC, &'w ComponentD);
 type GenericTupleQuery<'w, T, P> = (&'w T, &'w P);
+type CustomMutQuery<'w, 's> =
+    Query<'w, 's, CustomQuery<ComponentC, ComponentD>, CustomQueryFilter<ComponentC, ComponentD>>;
 
 fn main() {
     App::new()
+        .register_type::<ComponentA>()
+        .register_type::<ComponentB>()
+        .register_type::<ComponentC>()
+        .register_type::<ComponentD>()
+        .register_type::<ComponentZ>()
+        .init_resource::<ReadOnlyQueryTicks>()
         .add_systems(Startup, spawn)
         .add_systems(
             Update,
@@ -95,6 +133,7 @@ fn main() {
                 print_components_iter_mut,
                 print_components_iter,
                 print_components_tuple,
+                explain_custom_query,
             )
                 .chain(),
         )
@@ -104,46 +143,54 @@ fn main() {
 fn spawn(mut commands: Commands) {
     // z 不被加入
     commands.spawn((ComponentA, ComponentB, ComponentC, ComponentD));
+    // 缺少 D 并且有 Z, 查询不到, 用 explain_custom_query 查看原因
+    commands.spawn((ComponentA, ComponentC, ComponentZ));
 }
 
 // 只读的查询
+// 只读查询可以和 Query<EntityRef> 同时存在,用 EntityPrinter 打印实体上所有反射组件
 fn print_components_read_only(
     query: Query<
         ReadOnlyCustomQuery<ComponentC, ComponentD>,
         CustomQueryFilter<ComponentC, ComponentD>,
     >,
+    entities: Query<EntityRef>,
+    registry: Res<AppTypeRegistry>,
+    ticks: SystemChangeTick,
+    mut query_ticks: ResMut<ReadOnlyQueryTicks>,
 ) {
     println!("print components (read_only)");
+    query_ticks.last_run = ticks.last_run();
+    query_ticks.this_run = ticks.this_run();
+    let registry = registry.read();
+    let printer = EntityPrinter::json();
     for e in &query {
-        println!("Entity: {:?}", e.entity);
-        println!("A: {:?}", e.a);
-        println!("B: {:?}", e.b);
-        println!("Nested: {:?}", e.nested);
-        println!("Optional nested: {:?}", e.optional_nested);
-        println!("Optional tuple: {:?}", e.optional_tuple);
-        println!("Generic: {:?}", e.generic);
-        println!("enpty: {:?}", e.enpty);
+        if let Ok(entity) = entities.get(e.entity) {
+            printer.print_entity_ref(entity, &registry);
+        }
     }
     println!()
 }
 
 // 当使用可写的版本时,需要两个地方加mut
+// 可写查询与 Query<EntityRef> 冲突,用 ParamSet 先修改再打印
 fn print_components_iter_mut(
-    mut query: Query<
-        CustomQuery<ComponentC, ComponentD>,
-        CustomQueryFilter<ComponentC, ComponentD>,
-    >,
+    mut queries: ParamSet<(CustomMutQuery, Query<EntityRef>)>,
+    registry: Res<AppTypeRegistry>,
 ) {
     println!("print components (iter mut)");
-    for e in &mut query {
+    let mut matched = Vec::new();
+    for e in &mut queries.p0() {
         let e: CustomQueryItem<'_, '_, _, _> = e;
-        println!("Entity: {:?}", e.entity);
-        println!("A: {:?}", e.a);
-        println!("B: {:?}", e.b);
-        println!("Nested: {:?}", e.nested);
-        println!("Optional nested: {:?}", e.optional_nested);
-        println!("Optional tuple: {:?}", e.optional_tuple);
-        println!("Generic: {:?}", e.generic);
+        matched.push(e.entity);
+    }
+    let registry = registry.read();
+    let printer = EntityPrinter::default();
+    let entities = queries.p1();
+    for entity in matched {
+        if let Ok(entity) = entities.get(entity) {
+            printer.print_entity_ref(entity, &registry);
+        }
     }
 }
 //  这是只读版本
@@ -190,3 +237,22 @@ fn print_components_tuple(
         println!("generic_d {:?}", generic_d);
     }
 }
+
+// 解释每个实体为什么(没有)被 print_components_read_only 查询到
+// Added / Changed 的结果以 print_components_read_only 本帧运行时的 tick 为准
+fn explain_custom_query(
+    world: &World,
+    entities: Query<Entity, With<ComponentA>>,
+    ticks: Res<ReadOnlyQueryTicks>,
+) {
+    println!("explain components (read_only):");
+    for entity in &entities {
+        if let Ok(explanation) = explain::<
+            ReadOnlyCustomQuery<ComponentC, ComponentD>,
+            CustomQueryFilter<ComponentC, ComponentD>,
+        >(world, entity, ticks.last_run, ticks.this_run)
+        {
+            println!("{explanation}");
+        }
+    }
+}
@@ -1,183 +1,233 @@
 //! combinations 组合
 //! 遍历查询结果到组合
+//! Barnes–Hut 八叉树近似求解引力 O(n log n), T 键在精确解、并行精确解、近似解之间切换, [ ] 调整张角 θ
+//! 碰撞合并 两个球体相交时完全非弹性合并, 质量与动量守恒
+//! 积分器 I 键切换 位置 Verlet / 速度 Verlet / 辛欧拉 / RK4, 每步发布动能、势能、动量诊断
+//! 近距离交会 U 键开启自动拆分子步 (默认关闭), 每帧发布子步数诊断, - = 调整 Plummer 软化长度 ε
+//! 场景 1-5 键切换内置预设, S 键把当前状态保存为 RON, C 键保存为 CSV (assets/scenarios)
+//! 也可以从命令行指定预设名或场景文件
+//! 轨迹 每 4 个固定步记录一次, 画成渐隐的轨迹, R 键开关记录, E 键导出 CSV 和二进制文件 (assets/trajectories)
+//! cargo run --example ch9_iter_combinations -- disk_galaxy
+//! cargo run --example ch9_iter_combinations -- assets/scenarios/sun_earth_moon.csv
+//! 轨道预测 鼠标左键选中天体, 用幽灵模拟画出它未来 10 秒的路径, 点击空白处取消
+//! 相机 V 键切换 旋转 / 跟随 / 自由飞行, 跟随模式下跟随选中的天体, Tab 切换目标, 右键拖动旋转, 滚轮缩放
+//!
+//! 物理部分在 blibli_bevy2::nbody 中, 不依赖渲染, 可以在 MinimalPlugins 下运行
+//! 这里只是加上渲染和键盘控制
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::{
+    asset::io::file::FileAssetReader, diagnostic::LogDiagnosticsPlugin,
+    input::common_conditions::input_just_pressed, prelude::*,
+};
+use blibli_bevy2::camera_controller::{CameraController, CameraControllerPlugin};
+use blibli_bevy2::nbody::{
+    BodyColor, BodySpec, EnergyDiagnostics, ForceSolver, Integrator, Mass, NBodyPlugin,
+    OrbitPrediction, Preset, Scenario, ScenarioError, Star, Substepping, TrajectoryRecorder,
+    Velocity, generate_bodies, render::NBodyRenderPlugin,
+};
+
+const TRAIL_STRIDE: u64 = 4; // 每 4 个固定步记录一帧
+const TRAIL_FRAMES: usize = 256; // 保留的帧数
+const PREDICTION_SECONDS: f32 = 10.0; // 预测时长
+
+fn main() -> Result<(), ScenarioError> {
+    // 参数可以是预设名, 也可以是 .csv / .ron 文件
+    let scenario = match std::env::args().nth(1) {
+        Some(arg) => match Preset::from_name(&arg) {
+            Some(preset) => preset.scenario(),
+            None => Scenario::load(&arg)?,
+        },
+        None => Scenario::default(),
+    };
 
-use bevy::{color::palettes::css::ORANGE_RED, math::FloatPow, prelude::*};
-use rand::{Rng, SeedableRng};
-use rand_chacha::ChaCha8Rng;
-
-const GRAVITY_CONSTANT: f32 = 0.001; // 重力常数
-const NUM_BODIES: usize = 100; // 球体数量
-
-#[derive(Component, Default)]
-struct Mass(f32); // 质量
-
-#[derive(Component, Default, Deref, DerefMut)]
-struct Acceleration(Vec3); // 速度
-
-#[derive(Component, Default, Deref, DerefMut)]
-struct LastPos(Vec3);
-
-#[derive(Component)]
-struct Star;
-
-#[derive(Bundle, Default)]
-struct BodyBundle {
-    mesh: Mesh3d,
-    material: MeshMaterial3d<StandardMaterial>,
-    mass: Mass,
-    last_pos: LastPos,
-    acceleration: Acceleration,
-}
-
-fn main() {
     App::new()
-        .add_plugins(DefaultPlugins)
-        // 黑色背景
-        .insert_resource(ClearColor(Color::BLACK))
+        .insert_resource(scenario)
+        .insert_resource(TrajectoryRecorder::new(TRAIL_STRIDE, TRAIL_FRAMES))
+        .insert_resource(OrbitPrediction::new(PREDICTION_SECONDS))
+        .init_resource::<EnergyDiagnostics>()
+        .add_plugins((
+            DefaultPlugins,
+            LogDiagnosticsPlugin::default(),
+            NBodyPlugin,
+            NBodyRenderPlugin,
+            CameraControllerPlugin,
+        ))
         .add_systems(Startup, generate_bodies)
-        // 物理计算
-        .add_systems(FixedUpdate, (interact_bodies, integrate))
-        .add_systems(Update, look_at_star)
+        .add_systems(
+            Update,
+            (
+                toggle_solver.run_if(input_just_pressed(KeyCode::KeyT)),
+                adjust_theta(0.1).run_if(input_just_pressed(KeyCode::BracketRight)),
+                adjust_theta(-0.1).run_if(input_just_pressed(KeyCode::BracketLeft)),
+                cycle_integrator.run_if(input_just_pressed(KeyCode::KeyI)),
+                toggle_substepping.run_if(input_just_pressed(KeyCode::KeyU)),
+                adjust_softening(0.05).run_if(input_just_pressed(KeyCode::Equal)),
+                adjust_softening(-0.05).run_if(input_just_pressed(KeyCode::Minus)),
+                load_preset,
+                save_scenario::<false>.run_if(input_just_pressed(KeyCode::KeyS)),
+                save_scenario::<true>.run_if(input_just_pressed(KeyCode::KeyC)),
+                toggle_recording.run_if(input_just_pressed(KeyCode::KeyR)),
+                export_trajectories.run_if(input_just_pressed(KeyCode::KeyE)),
+                pick_body.run_if(input_just_pressed(MouseButton::Left)),
+            ),
+        )
         .run();
+    Ok(())
 }
 
-fn generate_bodies(
+// 删除所有天体, 按预设重新生成
+fn load_preset(
     mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    bodies: Query<Entity, With<Mass>>,
     time: Res<Time<Fixed>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    recorder: Option<ResMut<TrajectoryRecorder>>,
 ) {
-    // Sphere::new(1.0) 创建半径为1的球体参数
-    // .mesh() 生成 MeshBuilder (网格构建器)
-    // .ico(3) 用 ico 球算法细分3次
-    // ico (0 20面 1 80面 2 320面 3 1280面 4 5120面)
-    // 加入到资源中,用于后续渲染
-    let mesh = meshes.add(Sphere::new(1.0).mesh().ico(3).unwrap());
-
-    // 随机颜色范围
-    let color_range = 0.5..1.0;
-
-    let vel_range = -0.5..0.5;
-
-    // 随机种子
-    let mut rng = ChaCha8Rng::seed_from_u64(19878367467713);
-
-    for _ in 0..NUM_BODIES {
-        // 随机半径 在浮点数中 0.1..0.7 表示区间,不能直接转化为数组
-        let readus: f32 = rng.random_range(0.1..0.7);
-        // 体积 ∝ 半径³
-        let mass_value = FloatPow::cubed(readus) * 10.;
-
-        // 随机生成坐标
-        let position = Vec3::new(
-            rng.random_range(-1.0..1.0),
-            rng.random_range(-1.0..1.0),
-            rng.random_range(-1.0..1.0),
-        )
-        .normalize()
-            * ops::cbrt(rng.random_range(0.2f32..1.0))
-            * 15.0;
-
-        // 创建 entity
-        commands.spawn((
-            BodyBundle {
-                mesh: Mesh3d(mesh.clone()),
-                material: MeshMaterial3d(materials.add(Color::srgb(
-                    rng.random_range(color_range.clone()),
-                    rng.random_range(color_range.clone()),
-                    rng.random_range(color_range.clone()),
-                ))),
-                mass: Mass(mass_value),
-                acceleration: Acceleration(Vec3::ZERO),
-                last_pos: LastPos(
-                    position
-                        - Vec3::new(
-                            rng.random_range(vel_range.clone()),
-                            rng.random_range(vel_range.clone()),
-                            rng.random_range(vel_range.clone()),
-                        ) * time.timestep().as_secs_f32(),
-                ),
-            },
-            Transform {
-                translation: position,
-                scale: Vec3::splat(readus),
-                ..default()
-            },
-        ));
+    let mut keys_presets = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+    ]
+    .into_iter()
+    .zip(Preset::ALL);
+    let Some((_, preset)) = keys_presets.find(|(key, _)| keys.just_pressed(*key)) else {
+        return;
+    };
+
+    for entity in &bodies {
+        commands.entity(entity).despawn();
     }
-    // 创建小恒星
-    let star_radius = 1.1;
-
-    commands
-        .spawn((
-            BodyBundle {
-                mesh: Mesh3d(meshes.add(Sphere::new(1.0).mesh().ico(5).unwrap())),
-                material: MeshMaterial3d(materials.add(StandardMaterial {
-                    base_color: ORANGE_RED.into(),
-                    emissive: LinearRgba::from(ORANGE_RED) * 2.,
-                    ..default()
-                })),
-                mass: Mass(500.0),
-                ..default()
-            },
-            Transform::from_scale(Vec3::splat(star_radius)),
-            Star,
-        ))
-        // 添加光源
-        .with_child(PointLight {
-            color: Color::WHITE,
-            range: 100.0,
-            radius: star_radius,
-            ..default()
-        });
-
-    // 创建相机
-    // 相机位置 Transform::from_xyz(0.0, 10.5, -30.0)
-    // 相机朝向 .look_at(Vec3::ZERO, Vec3::Y) 看向ZERO 点位, 摄像头顶部朝向Y轴
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_xyz(0.0, 10.5, -30.0).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
+    if let Some(mut recorder) = recorder {
+        recorder.clear();
+    }
+    let scenario = preset.scenario();
+    scenario.spawn(&mut commands, time.timestep().as_secs_f32());
+    commands.insert_resource(scenario);
+    info!("scenario: {}", preset.name());
+}
+
+// 当前的位置和速度保存为新的场景文件
+fn save_scenario<const CSV: bool>(
+    bodies: Query<(&Mass, &Transform, &Velocity, &BodyColor, Has<Star>)>,
+) -> Result {
+    let scenario: Scenario = bodies
+        .iter()
+        .map(|(mass, transform, velocity, color, star)| BodySpec {
+            mass: mass.0,
+            position: transform.translation,
+            velocity: velocity.0,
+            radius: transform.scale.x,
+            color: color.to_srgba().to_f32_array_no_alpha(),
+            star,
+        })
+        .collect();
+
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let extension = if CSV { "csv" } else { "ron" };
+    let path = FileAssetReader::get_base_path()
+        .join("assets/scenarios")
+        .join(format!("snapshot_{secs}.{extension}"));
+    scenario.save(&path)?;
+    info!(
+        "saved {} bodies to {}",
+        scenario.bodies.len(),
+        path.display()
+    );
+    Ok(())
 }
 
-fn interact_bodies(mut query: Query<(&Mass, &GlobalTransform, &mut Acceleration)>) {
-    let mut iter = query.iter_combinations_mut();
-    // 两两组合
-    while let Some(
-        [
-            (Mass(m1), transform1, mut acc1),
-            (Mass(m2), transform2, mut acc2),
-        ],
-    ) = iter.fetch_next()
-    {
-        let delta = transform2.translation() - transform1.translation();
-        let distance_sq: f32 = delta.length_squared();
-        let f = GRAVITY_CONSTANT / distance_sq;
-        let force_unit_mass = delta * f;
-        acc1.0 += force_unit_mass * *m2;
-        acc2.0 -= force_unit_mass * *m1;
+// 从相机发出射线, 选中最近的相交球体
+fn pick_body(
+    window: Single<&Window>,
+    camera: Single<(&Camera, &GlobalTransform)>,
+    bodies: Query<(Entity, &Transform), With<Mass>>,
+    mut prediction: ResMut<OrbitPrediction>,
+    mut controller: Single<&mut CameraController>,
+) {
+    let (camera, camera_transform) = *camera;
+    let Some(ray) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor).ok())
+    else {
+        return;
+    };
+
+    let hit = bodies
+        .iter()
+        .filter_map(|(entity, transform)| {
+            let to_center = transform.translation - ray.origin;
+            let t = to_center.dot(*ray.direction);
+            let miss = to_center.length_squared() - t * t;
+            (t > 0.0 && miss <= transform.scale.x * transform.scale.x).then_some((entity, t))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity);
+    prediction.select(hit);
+    if hit.is_some() {
+        controller.target = hit;
     }
 }
 
-// 将影响结果进行渲染
-fn integrate(time: Res<Time>, mut query: Query<(&mut Acceleration, &mut Transform, &mut LastPos)>) {
-    let dt_sq = time.delta_secs() * time.delta_secs();
-    for (mut acceleration, mut transform, mut last_pos) in &mut query {
-        let new_pos = transform.translation * 2.0 - **last_pos + **acceleration * dt_sq;
-        **acceleration = Vec3::ZERO;
-        **last_pos = transform.translation;
-        transform.translation = new_pos;
+fn toggle_recording(mut commands: Commands, recorder: Option<Res<TrajectoryRecorder>>) {
+    if recorder.is_some() {
+        commands.remove_resource::<TrajectoryRecorder>();
+        info!("recording: off");
+    } else {
+        commands.insert_resource(TrajectoryRecorder::new(TRAIL_STRIDE, TRAIL_FRAMES));
+        info!("recording: on");
     }
 }
 
-// 相机会缓慢的 (lerp 插值运算) 朝向 小恒心
-fn look_at_star(
-    mut camera: Single<&mut Transform, (Without<Star>, With<Camera>)>,
-    star: Single<&Transform, With<Star>>,
-) {
-    let new_rotation = camera
-        .looking_at(star.translation, Vec3::Y)
-        .rotation
-        .lerp(camera.rotation, 2.0);
-    camera.rotation = new_rotation;
+// 同时导出 CSV 和二进制两种格式
+fn export_trajectories(recorder: Option<Res<TrajectoryRecorder>>) -> Result {
+    let Some(recorder) = recorder else {
+        return Ok(());
+    };
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let dir = FileAssetReader::get_base_path().join("assets/trajectories");
+    for extension in ["csv", "bin"] {
+        let path = dir.join(format!("run_{secs}.{extension}"));
+        recorder.save(&path)?;
+        info!(
+            "saved {} frames to {}",
+            recorder.frames().len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn cycle_integrator(mut integrator: ResMut<Integrator>) {
+    *integrator = integrator.next();
+    info!("integrator: {:?}", *integrator);
+}
+
+fn toggle_substepping(mut substepping: ResMut<Substepping>) {
+    substepping.enabled = !substepping.enabled;
+    info!("substepping: {}", substepping.enabled);
+}
+
+// step 为正时增大 ε, 为负时减小
+fn adjust_softening(step: f32) -> impl FnMut(ResMut<ForceSolver>) {
+    move |mut solver| {
+        solver.softening = (solver.softening + step).clamp(0.0, 1.0);
+        info!("softening: ε={:.2}", solver.softening);
+    }
+}
+
+fn toggle_solver(mut solver: ResMut<ForceSolver>) {
+    solver.kind = solver.kind.next();
+    info!("solver: {:?} θ={:.1}", solver.kind, solver.theta);
+}
+
+// step 为正时增大 θ, 为负时减小
+fn adjust_theta(step: f32) -> impl FnMut(ResMut<ForceSolver>) {
+    move |mut solver| {
+        solver.theta = (solver.theta + step).clamp(0.0, ForceSolver::MAX_THETA);
+        info!("solver: {:?} θ={:.1}", solver.kind, solver.theta);
+    }
 }
@@ -0,0 +1,154 @@
+//! 短程对势 PairInteraction
+//! 无头分子动力学: 216 个粒子放在有反射墙的盒子里, 速度 Verlet 积分, 定期打印温度和能量
+//! 对势只在截断半径内计算, cell list 找邻居, 不需要 iter_combinations 遍历所有组合
+//! 约化单位: 质量、σ、ε、玻尔兹曼常数都为 1, 温度 T = 2K / 3N
+//!
+//! cargo run --example ch9_pair_interaction -- lj
+//! 参数可选 lj (Lennard-Jones, 默认) / soft (软排斥) / spring (弹簧)
+
+use std::time::Duration;
+
+use bevy::{app::ScheduleRunnerPlugin, prelude::*, time::TimeUpdateStrategy};
+use blibli_bevy2::pair_interaction::{
+    LennardJones, PairForce, PairInteractionPlugin, PairPotentialEnergy, PairSystems,
+    SoftRepulsion, Spring,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const LATTICE: usize = 6; // 每条边的粒子数
+const SPACING: f32 = 1.2; // 初始晶格间距
+const BOX_SIZE: f32 = LATTICE as f32 * SPACING; // 盒子边长
+const INITIAL_TEMPERATURE: f32 = 1.0;
+const TIMESTEP: f64 = 0.005;
+const STEPS: u32 = 4000;
+const REPORT_EVERY: u32 = 200;
+
+#[derive(Component, Default, Deref, DerefMut)]
+struct Velocity(Vec3);
+
+fn main() {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::ZERO)),
+        PairInteractionPlugin::<LennardJones>::default(),
+        PairInteractionPlugin::<SoftRepulsion>::default(),
+        PairInteractionPlugin::<Spring>::default(),
+    ))
+    // 每帧正好一个固定步, 结果与机器速度无关
+    .insert_resource(Time::<Fixed>::from_seconds(TIMESTEP))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        TIMESTEP,
+    )))
+    .add_systems(Startup, spawn_particles)
+    .add_systems(FixedUpdate, integrate.after(PairSystems::Forces));
+
+    // 只插入选中的对势, 其余插件的 system 不会运行
+    match std::env::args().nth(1).as_deref() {
+        Some("soft") => app.insert_resource(SoftRepulsion {
+            strength: 25.0,
+            radius: 1.0,
+        }),
+        Some("spring") => app.insert_resource(Spring {
+            stiffness: 10.0,
+            rest_length: SPACING,
+            cutoff: 1.5,
+        }),
+        _ => app.insert_resource(LennardJones::default()),
+    };
+    app.run();
+}
+
+// 简单立方晶格, 随机速度缩放到初始温度, 总动量为 0
+fn spawn_particles(mut commands: Commands) {
+    let mut rng = ChaCha8Rng::seed_from_u64(19878367467713);
+    let mut particles = Vec::new();
+    for x in 0..LATTICE {
+        for y in 0..LATTICE {
+            for z in 0..LATTICE {
+                let position = (Vec3::new(x as f32, y as f32, z as f32) + 0.5) * SPACING;
+                let velocity = Vec3::new(
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                );
+                particles.push((position, velocity));
+            }
+        }
+    }
+
+    let n = particles.len() as f32;
+    let drift = particles.iter().map(|(_, v)| *v).sum::<Vec3>() / n;
+    let temperature = particles
+        .iter()
+        .map(|(_, v)| (*v - drift).length_squared())
+        .sum::<f32>()
+        / (3.0 * n);
+    let scale = (INITIAL_TEMPERATURE / temperature).sqrt();
+
+    for (position, velocity) in particles {
+        commands.spawn((
+            Transform::from_translation(position),
+            Velocity((velocity - drift) * scale),
+            PairForce::default(),
+        ));
+    }
+}
+
+// 速度 Verlet 拆成 踢-漂-踢: 上一步的后半个踢和这一步的前半个踢都用这一步算出的力
+// 两次踢之间速度和位置同步, 在这里测量温度和能量
+fn integrate(
+    time: Res<Time>,
+    energy: Res<PairPotentialEnergy>,
+    mut query: Query<(&mut Transform, &mut Velocity, &mut PairForce)>,
+    mut steps: Local<u32>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    let dt = time.delta_secs();
+    if *steps > 0 {
+        for (_, mut velocity, force) in &mut query {
+            velocity.0 += force.0 * (0.5 * dt);
+        }
+    }
+
+    if steps.is_multiple_of(REPORT_EVERY) {
+        let n = query.iter().len() as f32;
+        let kinetic: f32 = query
+            .iter()
+            .map(|(_, velocity, _)| 0.5 * velocity.length_squared())
+            .sum();
+        println!(
+            "step {:>5}  T = {:.4}  K/N = {:.4}  U/N = {:.4}  E/N = {:.4}",
+            *steps,
+            2.0 * kinetic / (3.0 * n),
+            kinetic / n,
+            energy.0 / n,
+            (kinetic + energy.0) / n,
+        );
+    }
+    if *steps == STEPS {
+        exit.write(AppExit::Success);
+        return;
+    }
+
+    for (mut transform, mut velocity, mut force) in &mut query {
+        velocity.0 += force.0 * (0.5 * dt);
+        transform.translation += velocity.0 * dt;
+        force.0 = Vec3::ZERO;
+        reflect(&mut transform.translation, &mut velocity.0);
+    }
+    *steps += 1;
+}
+
+// 碰到墙壁时位置镜像, 速度分量反向
+fn reflect(position: &mut Vec3, velocity: &mut Vec3) {
+    for axis in 0..3 {
+        if position[axis] < 0.0 {
+            position[axis] = -position[axis];
+            velocity[axis] = -velocity[axis];
+        } else if position[axis] > BOX_SIZE {
+            position[axis] = 2.0 * BOX_SIZE - position[axis];
+            velocity[axis] = -velocity[axis];
+        }
+    }
+}
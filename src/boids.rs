@@ -0,0 +1,456 @@
+//! Boids 群体行为
+//! 分离、对齐、聚集三条规则, 加上躲避障碍物和边界, 在 xy 平面上运动
+//! 邻居用 CellList 查找, 每个 boid 在 par_iter_mut 中只读快照、只写自己, 结果与线程数无关
+//! BoidSettings 实现了 Reflect, 可以在 inspector 中实时调整权重
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins((DefaultPlugins, BoidsPlugin))
+//!     // 每个 boid 需要 Transform + Boid, 障碍物需要 Transform + Obstacle
+//!     .add_systems(Startup, |mut commands: Commands| {
+//!         commands.spawn((Transform::default(), Boid::new(Vec2::X * 100.0)));
+//!     })
+//!     .run();
+//! ```
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    platform::collections::HashMap,
+    prelude::*,
+};
+
+use crate::pair_interaction::CellList;
+
+// 速度方向的一致程度, 所有速度单位向量之和的长度 / 数量, 1 表示完全同向
+pub const ALIGNMENT: DiagnosticPath = DiagnosticPath::const_new("boids/alignment");
+// 平均每个 boid 看到的邻居数
+pub const NEIGHBORS: DiagnosticPath = DiagnosticPath::const_new("boids/neighbors");
+// 平均速率
+pub const SPEED: DiagnosticPath = DiagnosticPath::const_new("boids/speed");
+// 到群体中心的平均距离
+pub const SPREAD: DiagnosticPath = DiagnosticPath::const_new("boids/spread");
+
+#[derive(Component, Debug, Default, Clone, Copy)]
+#[require(Transform)]
+pub struct Boid {
+    pub velocity: Vec2,
+    // 上一次转向时看到的邻居数
+    pub neighbors: u32,
+}
+
+impl Boid {
+    pub fn new(velocity: Vec2) -> Self {
+        Self {
+            velocity,
+            neighbors: 0,
+        }
+    }
+}
+
+// 圆形障碍物
+#[derive(Component, Debug, Clone, Copy)]
+#[require(Transform)]
+pub struct Obstacle {
+    pub radius: f32,
+}
+
+// 所有距离的单位是像素, 速度是像素/秒
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct BoidSettings {
+    // 能看到邻居的距离
+    pub perception: f32,
+    // 小于这个距离时开始分离
+    pub separation_distance: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub avoidance: f32,
+    pub containment: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    // 转向加速度的上限, 转弯半径约为 速率² / max_force
+    pub max_force: f32,
+    // 活动范围, 距离边界 margin 以内开始掉头
+    pub bounds: Rect,
+    pub margin: f32,
+}
+
+impl Default for BoidSettings {
+    fn default() -> Self {
+        Self {
+            perception: 50.0,
+            separation_distance: 20.0,
+            separation: 1.5,
+            alignment: 1.0,
+            cohesion: 1.0,
+            avoidance: 3.0,
+            containment: 2.0,
+            min_speed: 60.0,
+            max_speed: 150.0,
+            max_force: 400.0,
+            bounds: Rect::from_center_size(Vec2::ZERO, Vec2::new(1280.0, 720.0)),
+            margin: 60.0,
+        }
+    }
+}
+
+impl BoidSettings {
+    // 位于 position 的 boid 想要飞行的方向, 单位向量, 没有任何规则起作用时为 0
+    // neighbors 是感知范围内其他 boid 的 (位置, 速度)
+    // 每条规则给出一个单位方向, 按权重相加
+    pub fn desired_direction(
+        &self,
+        position: Vec2,
+        neighbors: impl Iterator<Item = (Vec2, Vec2)>,
+        obstacles: &[(Vec2, f32)],
+    ) -> Vec2 {
+        let mut count = 0;
+        let mut separation = Vec2::ZERO;
+        let mut heading = Vec2::ZERO;
+        let mut center = Vec2::ZERO;
+        for (other, other_velocity) in neighbors {
+            let delta = position - other;
+            let distance_sq = delta.length_squared();
+            if distance_sq < self.separation_distance * self.separation_distance {
+                // 越近推得越用力
+                separation += delta / distance_sq.max(1.0);
+            }
+            heading += other_velocity;
+            center += other;
+            count += 1;
+        }
+
+        let mut desired = separation.normalize_or_zero() * self.separation;
+        if count > 0 {
+            desired += heading.normalize_or_zero() * self.alignment;
+            desired += (center / count as f32 - position).normalize_or_zero() * self.cohesion;
+        }
+
+        // 进入障碍物周围 perception 范围后远离圆心
+        // 权重 perception / 间隙 - 1, 在范围边缘为 0, 贴近时远大于其他规则
+        for &(obstacle, radius) in obstacles {
+            let delta = position - obstacle;
+            let gap = delta.length() - radius;
+            if gap < self.perception {
+                desired +=
+                    delta.normalize_or_zero() * (self.avoidance * urgency(gap, self.perception));
+            }
+        }
+
+        // 距离边界 margin 以内朝内侧转向, 权重的算法与障碍物相同
+        let near = self.bounds.min;
+        let far = self.bounds.max;
+        for axis in 0..2 {
+            desired[axis] += self.containment
+                * (urgency(position[axis] - near[axis], self.margin)
+                    - urgency(far[axis] - position[axis], self.margin));
+        }
+
+        desired.normalize_or_zero()
+    }
+
+    // 朝 desired 转向 dt 秒后的速度
+    // 方向每秒最多转 max_force / 速率 弧度, 速率向目标速率靠拢, 急转弯时目标速率接近 min_speed
+    // 不直接加上 (期望速度 - 速度): 期望方向与速度相反时只会减速, 被 min_speed 拉回原方向后永远掉不了头
+    pub fn turn(&self, velocity: Vec2, desired: Vec2, dt: f32) -> Vec2 {
+        let max_speed = self.max_speed.max(self.min_speed);
+        let speed = velocity.length().clamp(self.min_speed, max_speed);
+        let mut heading = velocity.normalize_or(Vec2::X);
+        let mut target_speed = max_speed;
+        if desired != Vec2::ZERO {
+            let max_turn = self.max_force / speed.max(f32::EPSILON) * dt;
+            let angle = heading.angle_to(desired);
+            heading = Vec2::from_angle(angle.clamp(-max_turn, max_turn)).rotate(heading);
+            target_speed = self
+                .min_speed
+                .lerp(max_speed, 0.5 + 0.5 * heading.dot(desired));
+        }
+        let change = (target_speed - speed).clamp(-self.max_force * dt, self.max_force * dt);
+        heading * (speed + change)
+    }
+}
+
+// 距离 gap 小于 range 时 range / gap - 1, gap 不小于 1 避免除以 0
+fn urgency(gap: f32, range: f32) -> f32 {
+    if gap >= range {
+        return 0.0;
+    }
+    range / gap.max(1.0) - 1.0
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BoidSystems {
+    // 根据邻居计算新的速度
+    Steer,
+    // 按速度移动并朝向速度方向
+    Move,
+    // 发布群体指标
+    Diagnostics,
+}
+
+pub struct BoidsPlugin;
+
+impl Plugin for BoidsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BoidSettings>()
+            .register_type::<BoidSettings>()
+            .register_diagnostic(Diagnostic::new(ALIGNMENT))
+            .register_diagnostic(Diagnostic::new(NEIGHBORS))
+            .register_diagnostic(Diagnostic::new(SPEED))
+            .register_diagnostic(Diagnostic::new(SPREAD))
+            .configure_sets(
+                Update,
+                (
+                    BoidSystems::Steer,
+                    BoidSystems::Move,
+                    BoidSystems::Diagnostics,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    steer_boids.in_set(BoidSystems::Steer),
+                    move_boids.in_set(BoidSystems::Move),
+                    boid_diagnostics.in_set(BoidSystems::Diagnostics),
+                ),
+            );
+    }
+}
+
+// 先复制所有 boid 的位置和速度, 转向时只读这份快照, 先更新的 boid 不会影响后更新的
+fn steer_boids(
+    time: Res<Time>,
+    settings: Res<BoidSettings>,
+    obstacles: Query<(&Transform, &Obstacle)>,
+    mut boids: Query<(Entity, &Transform, &mut Boid)>,
+) {
+    let dt = time.delta_secs();
+    let positions: Vec<Vec3> = boids
+        .iter()
+        .map(|(_, transform, _)| transform.translation.with_z(0.0))
+        .collect();
+    let velocities: Vec<Vec2> = boids.iter().map(|(_, _, boid)| boid.velocity).collect();
+    let indices: HashMap<Entity, usize> = boids
+        .iter()
+        .enumerate()
+        .map(|(i, (entity, _, _))| (entity, i))
+        .collect();
+    let obstacles: Vec<(Vec2, f32)> = obstacles
+        .iter()
+        .map(|(transform, obstacle)| (transform.translation.truncate(), obstacle.radius))
+        .collect();
+    let perception = settings.perception.max(f32::EPSILON);
+    let cells = CellList::build(&positions, perception);
+
+    boids.par_iter_mut().for_each(|(entity, _, mut boid)| {
+        let index = indices[&entity];
+        let position = positions[index];
+        let mut count = 0;
+        let neighbors = cells
+            .neighbors(position)
+            .filter(|&j| {
+                j != index && positions[j].distance_squared(position) < perception * perception
+            })
+            .inspect(|_| count += 1)
+            .map(|j| (positions[j].truncate(), velocities[j]));
+        let desired = settings.desired_direction(position.truncate(), neighbors, &obstacles);
+        boid.velocity = settings.turn(boid.velocity, desired, dt);
+        boid.neighbors = count;
+    });
+}
+
+// 图形默认朝向 +x
+fn move_boids(time: Res<Time>, mut boids: Query<(&mut Transform, &Boid)>) {
+    let dt = time.delta_secs();
+    boids.par_iter_mut().for_each(|(mut transform, boid)| {
+        transform.translation += (boid.velocity * dt).extend(0.0);
+        transform.rotation = Quat::from_rotation_z(boid.velocity.to_angle());
+    });
+}
+
+fn boid_diagnostics(mut diagnostics: Diagnostics, boids: Query<(&Transform, &Boid)>) {
+    let count = boids.iter().len();
+    if count == 0 {
+        return;
+    }
+    let n = count as f32;
+    let mut heading = Vec2::ZERO;
+    let mut speed = 0.0;
+    let mut neighbors = 0;
+    let mut center = Vec2::ZERO;
+    for (transform, boid) in &boids {
+        heading += boid.velocity.normalize_or_zero();
+        speed += boid.velocity.length();
+        neighbors += boid.neighbors;
+        center += transform.translation.truncate();
+    }
+    center /= n;
+    let spread = boids
+        .iter()
+        .map(|(transform, _)| transform.translation.truncate().distance(center))
+        .sum::<f32>()
+        / n;
+
+    diagnostics.add_measurement(&ALIGNMENT, || (heading.length() / n) as f64);
+    diagnostics.add_measurement(&NEIGHBORS, || (neighbors as f32 / n) as f64);
+    diagnostics.add_measurement(&SPEED, || (speed / n) as f64);
+    diagnostics.add_measurement(&SPREAD, || spread as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 只保留一条规则, 边界足够大, 不会触发掉头
+    fn only(rule: impl FnOnce(&mut BoidSettings)) -> BoidSettings {
+        let mut settings = BoidSettings {
+            separation: 0.0,
+            alignment: 0.0,
+            cohesion: 0.0,
+            avoidance: 0.0,
+            containment: 0.0,
+            ..default()
+        };
+        rule(&mut settings);
+        settings
+    }
+
+    fn assert_direction(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected.normalize_or_zero(), 1e-5),
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn separation_pushes_away_from_close_neighbors() {
+        let settings = only(|s| s.separation = 1.0);
+        let desired = |neighbors: &[(Vec2, Vec2)]| {
+            settings.desired_direction(Vec2::ZERO, neighbors.iter().copied(), &[])
+        };
+        assert_direction(desired(&[(Vec2::new(5.0, 0.0), Vec2::ZERO)]), -Vec2::X);
+        // 近的邻居权重更大
+        let pushed = desired(&[
+            (Vec2::new(4.0, 0.0), Vec2::ZERO),
+            (Vec2::new(0.0, -16.0), Vec2::ZERO),
+        ]);
+        assert!(pushed.x < 0.0 && pushed.y > 0.0 && -pushed.x > pushed.y);
+        // 超出分离距离的邻居不起作用
+        assert_eq!(desired(&[(Vec2::new(30.0, 0.0), Vec2::ZERO)]), Vec2::ZERO);
+    }
+
+    #[test]
+    fn alignment_follows_average_heading() {
+        let settings = only(|s| s.alignment = 1.0);
+        let neighbors = [
+            (Vec2::new(30.0, 0.0), Vec2::new(10.0, 20.0)),
+            (Vec2::new(-30.0, 0.0), Vec2::new(-10.0, 20.0)),
+        ];
+        assert_direction(
+            settings.desired_direction(Vec2::ZERO, neighbors.into_iter(), &[]),
+            Vec2::Y,
+        );
+    }
+
+    #[test]
+    fn cohesion_steers_to_neighbor_center() {
+        let settings = only(|s| s.cohesion = 1.0);
+        let neighbors = [
+            (Vec2::new(10.0, 10.0), Vec2::X),
+            (Vec2::new(30.0, 10.0), -Vec2::X),
+        ];
+        assert_direction(
+            settings.desired_direction(Vec2::ZERO, neighbors.into_iter(), &[]),
+            Vec2::new(2.0, 1.0),
+        );
+        // 没有邻居时三条群体规则都不起作用
+        let all = only(|s| (s.separation, s.alignment, s.cohesion) = (1.0, 1.0, 1.0));
+        assert_eq!(
+            all.desired_direction(Vec2::ZERO, std::iter::empty(), &[]),
+            Vec2::ZERO
+        );
+    }
+
+    #[test]
+    fn avoids_obstacles_within_perception() {
+        let settings = only(|s| s.avoidance = 1.0);
+        let desired = |obstacle: (Vec2, f32)| {
+            settings.desired_direction(Vec2::ZERO, std::iter::empty(), &[obstacle])
+        };
+        // 间隙 30 < perception 50
+        assert_direction(desired((Vec2::new(50.0, 0.0), 20.0)), -Vec2::X);
+        assert_direction(desired((Vec2::new(0.0, -40.0), 30.0)), Vec2::Y);
+        // 间隙正好等于 perception
+        assert_eq!(desired((Vec2::new(70.0, 0.0), 20.0)), Vec2::ZERO);
+    }
+
+    #[test]
+    fn containment_turns_back_near_bounds() {
+        let settings = only(|s| s.containment = 1.0);
+        let (min, max) = (settings.bounds.min, settings.bounds.max);
+        let desired =
+            |position: Vec2| settings.desired_direction(position, std::iter::empty(), &[]);
+        assert_eq!(desired(Vec2::ZERO), Vec2::ZERO);
+        assert_direction(desired(Vec2::new(min.x + 10.0, 0.0)), Vec2::X);
+        assert_direction(desired(Vec2::new(max.x - 10.0, 0.0)), -Vec2::X);
+        assert_direction(desired(Vec2::new(0.0, max.y - 10.0)), -Vec2::Y);
+        // 越界后仍然指向内侧
+        assert_direction(desired(min - 100.0), Vec2::ONE);
+    }
+
+    #[test]
+    fn urgency_grows_as_gap_closes() {
+        assert_eq!(urgency(50.0, 50.0), 0.0);
+        assert_eq!(urgency(80.0, 50.0), 0.0);
+        assert_eq!(urgency(25.0, 50.0), 1.0);
+        assert_eq!(urgency(5.0, 50.0), 9.0);
+        // 贴近或穿过时不会除以 0
+        assert_eq!(urgency(0.0, 50.0), 49.0);
+        assert_eq!(urgency(-10.0, 50.0), 49.0);
+    }
+
+    #[test]
+    fn turn_rate_is_limited() {
+        let settings = BoidSettings::default();
+        let dt = 0.1;
+        // 速率 100 时每秒最多转 max_force / 100 = 4 弧度
+        let velocity = Vec2::X * 100.0;
+        for desired in [Vec2::Y, -Vec2::Y, Vec2::new(-1.0, 0.01).normalize()] {
+            let turned = settings.turn(velocity, desired, dt);
+            let angle = velocity.angle_to(turned);
+            assert!(
+                (angle.abs() - 0.4).abs() < 1e-4,
+                "turned {angle} towards {desired}"
+            );
+            assert_eq!(angle.signum(), desired.y.signum());
+            // 速率变化也受 max_force 限制
+            assert!((turned.length() - 100.0).abs() <= settings.max_force * dt + 1e-3);
+        }
+        // 小角度一步转到位, 同向时加速到 max_speed
+        let desired = Vec2::from_angle(0.1);
+        let turned = settings.turn(velocity, desired, dt);
+        assert_direction(turned.normalize(), desired);
+        assert!((turned.length() - 140.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn turn_keeps_speed_in_range() {
+        let settings = BoidSettings::default();
+        // 静止时沿 +x 以 min_speed 出发
+        let start = settings.turn(Vec2::ZERO, Vec2::ZERO, 0.0);
+        assert_eq!(start, Vec2::X * settings.min_speed);
+        let fast = settings.turn(Vec2::Y * 1000.0, Vec2::ZERO, 0.1);
+        assert_direction(fast.normalize(), Vec2::Y);
+        assert!((fast.length() - settings.max_speed).abs() < 1e-3);
+        // 掉头时减速, 但不低于 min_speed
+        let mut velocity = Vec2::X * settings.max_speed;
+        for _ in 0..100 {
+            velocity = settings.turn(velocity, -Vec2::X, 1.0 / 60.0);
+            let speed = velocity.length();
+            assert!(speed >= settings.min_speed - 1e-3 && speed <= settings.max_speed + 1e-3);
+        }
+        assert_direction(velocity.normalize(), -Vec2::X);
+    }
+}
@@ -0,0 +1,212 @@
+//! 2D 精灵活动的范围, 以及越界时的行为
+//! Bounds 不依赖 Window, 有窗口时由 example 跟随窗口大小, 无头运行时使用默认大小
+//!
+//! ```ignore
+//! let bounds = Bounds {
+//!     mode: BoundaryMode::SlowDown { restitution: 0.5 },
+//!     ..default()
+//! };
+//! if !bounds.apply(&mut position, &mut velocity) {
+//!     commands.entity(entity).despawn();
+//! }
+//! ```
+
+use bevy::prelude::*;
+
+// 越界时的行为
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub enum BoundaryMode {
+    // 只反转越界那个轴的速度, 并把位置拉回边界上
+    Reflect,
+    // 从另一侧出现
+    Wrap,
+    // 删除
+    Absorb,
+    // 和 Reflect 一样, 但速度乘以 restitution
+    SlowDown { restitution: f32 },
+}
+
+impl BoundaryMode {
+    pub fn next(self) -> Self {
+        match self {
+            BoundaryMode::Reflect => BoundaryMode::Wrap,
+            BoundaryMode::Wrap => BoundaryMode::Absorb,
+            BoundaryMode::Absorb => BoundaryMode::SlowDown { restitution: 0.5 },
+            BoundaryMode::SlowDown { .. } => BoundaryMode::Reflect,
+        }
+    }
+}
+
+// 精灵活动的范围
+#[derive(Resource, Debug, Clone, Copy, Reflect)]
+#[reflect(Resource)]
+pub struct Bounds {
+    pub rect: Rect,
+    pub mode: BoundaryMode,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            rect: Rect::from_center_size(Vec2::ZERO, Vec2::new(1280.0, 720.0)),
+            mode: BoundaryMode::Reflect,
+        }
+    }
+}
+
+impl Bounds {
+    // 处理后位置一定在范围内, 返回 false 表示应该删除
+    pub fn apply(&self, position: &mut Vec2, velocity: &mut Vec2) -> bool {
+        if self.rect.contains(*position) {
+            return true;
+        }
+        let (min, max) = (self.rect.min, self.rect.max);
+        match self.mode {
+            BoundaryMode::Absorb => return false,
+            BoundaryMode::Wrap => {
+                // rem_euclid 的结果可能因为舍入等于 size, 正好落在 max 上, 仍在范围内
+                *position = min + (*position - min).rem_euclid(self.rect.size());
+            }
+            BoundaryMode::Reflect | BoundaryMode::SlowDown { .. } => {
+                // 速度直接指向内侧, 而不是取反, 下一帧还没回到范围内也不会来回抖动
+                for axis in 0..2 {
+                    if position[axis] < min[axis] {
+                        velocity[axis] = velocity[axis].abs();
+                    } else if position[axis] > max[axis] {
+                        velocity[axis] = -velocity[axis].abs();
+                    }
+                }
+                *position = position.clamp(min, max);
+                if let BoundaryMode::SlowDown { restitution } = self.mode {
+                    *velocity *= restitution;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [BoundaryMode; 4] = [
+        BoundaryMode::Reflect,
+        BoundaryMode::Wrap,
+        BoundaryMode::Absorb,
+        BoundaryMode::SlowDown { restitution: 0.5 },
+    ];
+
+    // 每条边、每个角都越界, 包括越过好几倍宽度和刚好越过一点
+    fn overshoots(rect: Rect) -> Vec<(Vec2, Vec2)> {
+        let (min, max, size) = (rect.min, rect.max, rect.size());
+        let mut cases = Vec::new();
+        for x in [
+            min.x - 0.001,
+            min.x - 30.0,
+            max.x + 5.0,
+            max.x + 3.5 * size.x,
+        ] {
+            cases.push((Vec2::new(x, 0.0), Vec2::new(7.0, -3.0)));
+            cases.push((Vec2::new(x, 0.0), Vec2::new(-7.0, 3.0)));
+        }
+        for y in [
+            min.y - 12.0,
+            max.y + 0.25,
+            max.y + 2.0 * size.y,
+            min.y - 9.0 * size.y,
+        ] {
+            cases.push((Vec2::new(10.0, y), Vec2::new(2.0, 4.0)));
+            cases.push((Vec2::new(-10.0, y), Vec2::new(-2.0, -4.0)));
+        }
+        for corner in [min - 20.0, max + 20.0, Vec2::new(min.x - 1.0, max.y + 1.0)] {
+            cases.push((corner, Vec2::new(-5.0, 5.0)));
+            cases.push((corner, Vec2::new(5.0, -5.0)));
+        }
+        cases
+    }
+
+    #[test]
+    fn inside_is_unchanged() {
+        for mode in MODES {
+            let bounds = Bounds { mode, ..default() };
+            for position in [
+                Vec2::ZERO,
+                bounds.rect.min,
+                bounds.rect.max,
+                Vec2::new(600.0, -300.0),
+            ] {
+                let (mut p, mut v) = (position, Vec2::new(3.0, -4.0));
+                assert!(bounds.apply(&mut p, &mut v));
+                assert_eq!((p, v), (position, Vec2::new(3.0, -4.0)));
+            }
+        }
+    }
+
+    #[test]
+    fn overshoot_ends_inside() {
+        for mode in MODES {
+            let bounds = Bounds { mode, ..default() };
+            for (position, velocity) in overshoots(bounds.rect) {
+                let (mut p, mut v) = (position, velocity);
+                let keep = bounds.apply(&mut p, &mut v);
+                if mode == BoundaryMode::Absorb {
+                    assert!(!keep, "{mode:?} kept {position}");
+                    continue;
+                }
+                assert!(keep, "{mode:?} removed {position}");
+                assert!(
+                    bounds.rect.contains(p),
+                    "{mode:?} moved {position} to {p}, outside {:?}",
+                    bounds.rect
+                );
+            }
+        }
+    }
+
+    // 反弹后越界的轴上速度指向内侧, 另一轴不变, SlowDown 再乘以 restitution
+    #[test]
+    fn reflect_points_velocity_inward() {
+        for (mode, scale) in [
+            (BoundaryMode::Reflect, 1.0),
+            (BoundaryMode::SlowDown { restitution: 0.5 }, 0.5),
+        ] {
+            let bounds = Bounds { mode, ..default() };
+            let (min, max) = (bounds.rect.min, bounds.rect.max);
+            for (position, velocity) in overshoots(bounds.rect) {
+                let (mut p, mut v) = (position, velocity);
+                bounds.apply(&mut p, &mut v);
+                for axis in 0..2 {
+                    let expected = if position[axis] < min[axis] {
+                        velocity[axis].abs()
+                    } else if position[axis] > max[axis] {
+                        -velocity[axis].abs()
+                    } else {
+                        velocity[axis]
+                    };
+                    assert_eq!(v[axis], expected * scale, "{mode:?} at {position}");
+                }
+            }
+        }
+    }
+
+    // 环绕只改变位置, 移动的距离是范围大小的整数倍
+    #[test]
+    fn wrap_keeps_velocity_and_phase() {
+        let bounds = Bounds {
+            mode: BoundaryMode::Wrap,
+            ..default()
+        };
+        let size = bounds.rect.size();
+        for (position, velocity) in overshoots(bounds.rect) {
+            let (mut p, mut v) = (position, velocity);
+            bounds.apply(&mut p, &mut v);
+            assert_eq!(v, velocity);
+            let periods = (p - position) / size;
+            assert!(
+                periods.round().abs_diff_eq(periods, 1e-3),
+                "{position} -> {p}"
+            );
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! 3D 相机控制器
+//! 三种模式: 围绕固定点旋转、跟随选中的实体、自由飞行, V 键切换, Tab / Shift+Tab 切换跟随目标
+//! 右键拖动旋转视角, 滚轮缩放, 自由飞行时方向键移动, PageUp / PageDown 升降
+//! 相机不会直接跳到目标位置, 而是按 1 - e^(-smoothing·dt) 的比例逼近, 与帧率无关
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins((DefaultPlugins, CameraControllerPlugin))
+//!     .add_systems(Startup, |mut commands: Commands| {
+//!         commands.spawn((
+//!             Camera3d::default(),
+//!             CameraController::looking_from(Vec3::ZERO, Vec3::new(0.0, 10.0, -30.0)),
+//!         ));
+//!     });
+//! ```
+//!
+//! 变换的计算都是纯函数, 不需要渲染也可以使用
+
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{
+    ecs::system::SystemParam,
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll},
+    prelude::*,
+};
+
+// 俯仰角不能到 ±90°, 否则 yaw 失去意义
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+const MIN_DISTANCE: f32 = 0.5;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    // 围绕 focus 旋转
+    #[default]
+    Orbit,
+    // 围绕 target 旋转, focus 每帧更新为 target 的位置
+    Follow,
+    // 自由飞行
+    FreeFly,
+}
+
+impl CameraMode {
+    pub fn next(self) -> Self {
+        match self {
+            CameraMode::Orbit => CameraMode::Follow,
+            CameraMode::Follow => CameraMode::FreeFly,
+            CameraMode::FreeFly => CameraMode::Orbit,
+        }
+    }
+}
+
+// 可以被 Tab 切换跟随的实体
+#[derive(Component, Default)]
+pub struct CameraTarget;
+
+// 相机的朝向始终是 orbit_rotation(yaw, pitch)
+// 旋转模式下相机位于 focus 后方 distance 处, 自由飞行时位于 eye
+#[derive(Component, Debug, Clone)]
+#[require(Transform)]
+pub struct CameraController {
+    pub mode: CameraMode,
+    pub focus: Vec3,
+    pub target: Option<Entity>,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub eye: Vec3,
+    // 每秒逼近的速率, 越大越跟手
+    pub smoothing: f32,
+    // 自由飞行速度 (单位/秒)
+    pub fly_speed: f32,
+    // 每像素旋转的弧度
+    pub sensitivity: f32,
+    // 每格滚轮缩放的比例
+    pub zoom_speed: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::Orbit,
+            focus: Vec3::ZERO,
+            target: None,
+            yaw: 0.0,
+            pitch: 0.0,
+            distance: 10.0,
+            eye: Vec3::Z * 10.0,
+            smoothing: 10.0,
+            fly_speed: 10.0,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+        }
+    }
+}
+
+impl CameraController {
+    // 从 eye 看向 focus
+    pub fn looking_from(focus: Vec3, eye: Vec3) -> Self {
+        let (yaw, pitch, distance) = yaw_pitch_distance(focus, eye);
+        Self {
+            focus,
+            yaw,
+            pitch,
+            distance,
+            eye,
+            ..default()
+        }
+    }
+
+    // 当前参数下相机应该在的位置和朝向 (不含平滑)
+    pub fn desired_transform(&self) -> Transform {
+        let translation = match self.mode {
+            CameraMode::Orbit | CameraMode::Follow => {
+                orbit_eye(self.focus, self.yaw, self.pitch, self.distance)
+            }
+            CameraMode::FreeFly => self.eye,
+        };
+        Transform::from_translation(translation).with_rotation(orbit_rotation(self.yaw, self.pitch))
+    }
+
+    pub fn rotate(&mut self, delta: Vec2) {
+        self.yaw -= delta.x * self.sensitivity;
+        self.pitch = (self.pitch - delta.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn zoom(&mut self, scroll: f32) {
+        self.distance = (self.distance * ops::exp(-scroll * self.zoom_speed)).max(MIN_DISTANCE);
+    }
+
+    // 切换模式时保持相机位置不跳变
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        match (self.mode, mode) {
+            (CameraMode::Orbit | CameraMode::Follow, CameraMode::FreeFly) => {
+                self.eye = orbit_eye(self.focus, self.yaw, self.pitch, self.distance);
+            }
+            // 新的旋转中心在相机正前方 distance 处
+            (CameraMode::FreeFly, CameraMode::Orbit) => {
+                self.focus =
+                    self.eye - orbit_rotation(self.yaw, self.pitch) * Vec3::Z * self.distance;
+            }
+            _ => {}
+        }
+        self.mode = mode;
+    }
+}
+
+// 按键绑定, 可以在添加插件后替换
+#[derive(Resource, Debug, Clone)]
+pub struct CameraBindings {
+    pub cycle_mode: KeyCode,
+    pub cycle_target: KeyCode,
+    pub rotate: MouseButton,
+    pub forward: KeyCode,
+    pub back: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
+}
+
+impl Default for CameraBindings {
+    fn default() -> Self {
+        Self {
+            cycle_mode: KeyCode::KeyV,
+            cycle_target: KeyCode::Tab,
+            rotate: MouseButton::Right,
+            forward: KeyCode::ArrowUp,
+            back: KeyCode::ArrowDown,
+            left: KeyCode::ArrowLeft,
+            right: KeyCode::ArrowRight,
+            up: KeyCode::PageUp,
+            down: KeyCode::PageDown,
+        }
+    }
+}
+
+pub struct CameraControllerPlugin;
+
+impl Plugin for CameraControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraBindings>().add_systems(
+            Update,
+            (cycle_camera_mode, cycle_camera_target, control_camera).chain(),
+        );
+    }
+}
+
+// 绕 Y 轴 yaw, 再绕 X 轴 pitch, 相机看向 -Z
+pub fn orbit_rotation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0)
+}
+
+// 看向 focus 时相机的位置
+pub fn orbit_eye(focus: Vec3, yaw: f32, pitch: f32, distance: f32) -> Vec3 {
+    focus + orbit_rotation(yaw, pitch) * Vec3::Z * distance
+}
+
+// orbit_eye 的逆运算
+pub fn yaw_pitch_distance(focus: Vec3, eye: Vec3) -> (f32, f32, f32) {
+    let offset = eye - focus;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return (0.0, 0.0, 0.0);
+    }
+    let direction = offset / distance;
+    let pitch = ops::asin(-direction.y).clamp(-MAX_PITCH, MAX_PITCH);
+    let yaw = ops::atan2(direction.x, direction.z);
+    (yaw, pitch, distance)
+}
+
+// 每帧逼近的比例, 与帧率无关: 两帧各走 dt 等于一帧走 2·dt
+pub fn damping_factor(smoothing: f32, dt: f32) -> f32 {
+    1.0 - ops::exp(-smoothing * dt)
+}
+
+// 位置线性插值, 朝向球面插值
+pub fn damp_transform(current: &Transform, desired: &Transform, factor: f32) -> Transform {
+    Transform {
+        translation: current.translation.lerp(desired.translation, factor),
+        rotation: current.rotation.slerp(desired.rotation, factor),
+        scale: current.scale,
+    }
+}
+
+// 自由飞行的位移, input 是相机空间的方向 (x 右, y 上, -z 前)
+pub fn fly_offset(yaw: f32, pitch: f32, input: Vec3, speed: f32, dt: f32) -> Vec3 {
+    orbit_rotation(yaw, pitch) * input.normalize_or_zero() * speed * dt
+}
+
+fn cycle_camera_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<CameraBindings>,
+    mut controllers: Query<&mut CameraController>,
+) {
+    if !keys.just_pressed(bindings.cycle_mode) {
+        return;
+    }
+    for mut controller in &mut controllers {
+        let mut mode = controller.mode.next();
+        // 没有目标时跳过跟随模式
+        if mode == CameraMode::Follow && controller.target.is_none() {
+            mode = mode.next();
+        }
+        controller.set_mode(mode);
+        info!("camera: {:?}", controller.mode);
+    }
+}
+
+// 按 Entity 排序, 选中下一个 (Shift 上一个) 目标并切换到跟随模式
+fn cycle_camera_target(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<CameraBindings>,
+    mut controllers: Query<&mut CameraController>,
+    targets: Query<Entity, With<CameraTarget>>,
+) {
+    if !keys.just_pressed(bindings.cycle_target) {
+        return;
+    }
+    let mut targets: Vec<Entity> = targets.iter().collect();
+    if targets.is_empty() {
+        return;
+    }
+    targets.sort();
+    let backwards = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    for mut controller in &mut controllers {
+        let current = controller
+            .target
+            .and_then(|target| targets.iter().position(|entity| *entity == target));
+        let index = match (current, backwards) {
+            (Some(i), false) => (i + 1) % targets.len(),
+            (Some(i), true) => (i + targets.len() - 1) % targets.len(),
+            (None, false) => 0,
+            (None, true) => targets.len() - 1,
+        };
+        controller.target = Some(targets[index]);
+        controller.set_mode(CameraMode::Follow);
+        info!("camera target: {}", targets[index]);
+    }
+}
+
+#[derive(SystemParam)]
+struct CameraInput<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    motion: Res<'w, AccumulatedMouseMotion>,
+    scroll: Res<'w, AccumulatedMouseScroll>,
+    bindings: Res<'w, CameraBindings>,
+}
+
+impl CameraInput<'_> {
+    // 相机空间的移动方向
+    fn fly_direction(&self) -> Vec3 {
+        let axis = |positive: KeyCode, negative: KeyCode| {
+            self.keys.pressed(positive) as i8 as f32 - self.keys.pressed(negative) as i8 as f32
+        };
+        let bindings = &self.bindings;
+        Vec3::new(
+            axis(bindings.right, bindings.left),
+            axis(bindings.up, bindings.down),
+            axis(bindings.back, bindings.forward),
+        )
+    }
+}
+
+fn control_camera(
+    time: Res<Time>,
+    input: CameraInput,
+    mut cameras: Query<(&mut CameraController, &mut Transform)>,
+    targets: Query<&GlobalTransform>,
+) {
+    let dt = time.delta_secs();
+    let scroll = input.scroll.delta.y;
+
+    for (mut controller, mut transform) in &mut cameras {
+        if input.mouse.pressed(input.bindings.rotate) {
+            controller.rotate(input.motion.delta);
+        }
+
+        match controller.mode {
+            CameraMode::Orbit => controller.zoom(scroll),
+            CameraMode::Follow => {
+                controller.zoom(scroll);
+                // 目标被删除后停在原地围绕最后的位置旋转
+                match controller.target.map(|target| targets.get(target)) {
+                    Some(Ok(target)) => controller.focus = target.translation(),
+                    _ => {
+                        controller.target = None;
+                        controller.mode = CameraMode::Orbit;
+                    }
+                }
+            }
+            CameraMode::FreeFly => {
+                let offset = fly_offset(
+                    controller.yaw,
+                    controller.pitch,
+                    input.fly_direction(),
+                    controller.fly_speed,
+                    dt,
+                );
+                controller.eye += offset;
+            }
+        }
+
+        let factor = damping_factor(controller.smoothing, dt);
+        *transform = damp_transform(&transform, &controller.desired_transform(), factor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOCUS: Vec3 = Vec3::new(1.0, -2.0, 3.0);
+
+    #[test]
+    fn yaw_pitch_distance_inverts_orbit_eye() {
+        for (yaw, pitch, distance) in [
+            (0.0, 0.0, 10.0),
+            (1.2, 0.4, 3.5),
+            (-2.8, -1.1, 25.0),
+            (3.0, MAX_PITCH, 0.75),
+        ] {
+            let eye = orbit_eye(FOCUS, yaw, pitch, distance);
+            let (yaw2, pitch2, distance2) = yaw_pitch_distance(FOCUS, eye);
+            assert!((yaw2 - yaw).abs() < 1e-3, "yaw {yaw} -> {yaw2}");
+            assert!((pitch2 - pitch).abs() < 1e-3, "pitch {pitch} -> {pitch2}");
+            assert!((distance2 - distance).abs() < 1e-4 * distance);
+            // 反算的参数回到同一个位置
+            assert!(orbit_eye(FOCUS, yaw2, pitch2, distance2).abs_diff_eq(eye, 1e-3 * distance));
+        }
+    }
+
+    #[test]
+    fn damping_is_frame_rate_independent() {
+        let current = Transform::from_xyz(0.0, 0.0, 0.0);
+        let desired = Transform::from_xyz(10.0, -4.0, 2.0);
+        for (smoothing, dt) in [(10.0, 1.0 / 60.0), (3.0, 0.1), (25.0, 0.004)] {
+            let half = damping_factor(smoothing, dt);
+            let full = damping_factor(smoothing, 2.0 * dt);
+            // 两步之后剩下的距离比例是 (1 - f)²
+            assert!((1.0 - (1.0 - half) * (1.0 - half) - full).abs() < 1e-6);
+
+            let twice = damp_transform(&damp_transform(&current, &desired, half), &desired, half);
+            let once = damp_transform(&current, &desired, full);
+            assert!(twice.translation.abs_diff_eq(once.translation, 1e-4));
+        }
+    }
+
+    #[test]
+    fn set_mode_keeps_camera_in_place() {
+        let mut controller = CameraController::looking_from(FOCUS, Vec3::new(8.0, 5.0, -12.0));
+        controller.yaw += 0.3;
+        let start = controller.desired_transform();
+
+        controller.set_mode(CameraMode::FreeFly);
+        let free_fly = controller.desired_transform();
+        assert!(free_fly.translation.abs_diff_eq(start.translation, 1e-4));
+        assert_eq!(free_fly.rotation, start.rotation);
+
+        // 自由飞行一段距离后切回旋转模式, 旋转中心移到相机前方
+        controller.eye += Vec3::new(2.0, 1.0, 0.5);
+        let moved = controller.desired_transform();
+        controller.set_mode(CameraMode::Orbit);
+        let orbit = controller.desired_transform();
+        assert!(orbit.translation.abs_diff_eq(moved.translation, 1e-4));
+        assert_eq!(orbit.rotation, moved.rotation);
+        assert!((controller.focus.distance(orbit.translation) - controller.distance).abs() < 1e-4);
+    }
+}
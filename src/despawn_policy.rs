@@ -0,0 +1,270 @@
+//! 父实体被删除时如何处理子实体
+//! bevy 默认连同所有后代一起删除, 给父实体加上 DespawnPolicy 可以改为把子实体挂到祖父实体上, 或者变成根实体
+//! 保留下来的子实体世界变换不变
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins((DefaultPlugins, DespawnPolicyPlugin))
+//!     .add_systems(Startup, |mut commands: Commands| {
+//!         commands
+//!             .spawn((Transform::default(), DespawnPolicy::Reparent))
+//!             .with_child(Transform::from_xyz(1.0, 0.0, 0.0));
+//!     });
+//! ```
+
+use bevy::{ecs::world::DeferredWorld, prelude::*};
+
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub enum DespawnPolicy {
+    // 连同所有后代一起删除, 与没有这个组件时相同
+    #[default]
+    Cascade,
+    // 子实体挂到祖父实体上, 没有祖父实体时变成根实体
+    Reparent,
+    // 子实体都变成根实体
+    Detach,
+}
+
+impl DespawnPolicy {
+    pub fn next(self) -> Self {
+        match self {
+            DespawnPolicy::Cascade => DespawnPolicy::Reparent,
+            DespawnPolicy::Reparent => DespawnPolicy::Detach,
+            DespawnPolicy::Detach => DespawnPolicy::Cascade,
+        }
+    }
+}
+
+pub struct DespawnPolicyPlugin;
+
+impl Plugin for DespawnPolicyPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<DespawnPolicy>()
+            .add_observer(apply_despawn_policy);
+    }
+}
+
+// Despawn 观察者在所有组件的 on_despawn 钩子之前运行
+// Children 的 on_despawn 钩子会删除列表中的所有子实体, 所以先清空列表, 再用命令给子实体换父实体
+// 命令在父实体删除之后才执行, 那时替换或移除 ChildOf 不会再去修改已经不存在的父实体
+fn apply_despawn_policy(despawn: On<Despawn, DespawnPolicy>, mut world: DeferredWorld) {
+    let parent = despawn.entity;
+    let Some(&policy) = world.get::<DespawnPolicy>(parent) else {
+        return;
+    };
+    if policy == DespawnPolicy::Cascade {
+        return;
+    }
+    let Some(children) = world.get::<Children>(parent) else {
+        return;
+    };
+    let children: Vec<Entity> = children.to_vec();
+    let grandparent = match policy {
+        DespawnPolicy::Reparent => world.get::<ChildOf>(parent).map(ChildOf::parent),
+        _ => None,
+    };
+    let placements: Vec<(Entity, GlobalTransform)> = children
+        .iter()
+        .map(|&child| (child, world_transform(&world, child)))
+        .collect();
+
+    if let Some(mut children) = world.get_mut::<Children>(parent) {
+        children.collection_mut_risky().clear();
+    }
+    world.commands().queue(move |world: &mut World| {
+        // 祖父实体可能在同一次级联删除中已经被删除
+        let grandparent = grandparent.filter(|&entity| world.get_entity(entity).is_ok());
+        let grandparent_transform = grandparent.map(|entity| world_transform(world, entity));
+        for (child, global) in placements {
+            let Ok(mut child) = world.get_entity_mut(child) else {
+                continue;
+            };
+            match (grandparent, grandparent_transform) {
+                (Some(grandparent), Some(grandparent_transform)) => {
+                    child.insert((
+                        global.reparented_to(&grandparent_transform),
+                        ChildOf(grandparent),
+                    ));
+                }
+                _ => {
+                    child.insert(global.compute_transform()).remove::<ChildOf>();
+                }
+            }
+        }
+    });
+}
+
+// 沿 ChildOf 链组合 Transform, 不依赖在 PostUpdate 才更新的 GlobalTransform
+fn world_transform(world: &World, entity: Entity) -> GlobalTransform {
+    let mut global = GlobalTransform::IDENTITY;
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        let parent = world.get::<ChildOf>(entity).map(ChildOf::parent);
+        // 祖先已经在级联删除中被删除, 只能用上一次传播得到的 GlobalTransform
+        if parent.is_some_and(|parent| world.get_entity(parent).is_err()) {
+            let last = world.get::<GlobalTransform>(entity).copied();
+            return last.unwrap_or_default() * global;
+        }
+        let local = world.get::<Transform>(entity).copied().unwrap_or_default();
+        global = GlobalTransform::from(local) * global;
+        current = parent;
+    }
+    global
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Scene {
+        grandparent: Entity,
+        parent: Entity,
+        children: [Entity; 2],
+        grandchild: Entity,
+    }
+
+    // grandparent ── parent(policy) ─┬─ child ── grandchild
+    //                                └─ child
+    // 每一层都有平移、旋转和缩放
+    fn scene(app: &mut App, policy: DespawnPolicy) -> Scene {
+        let world = app.world_mut();
+        let grandparent = world
+            .spawn(
+                Transform::from_xyz(10.0, -2.0, 0.0)
+                    .with_rotation(Quat::from_rotation_z(0.3))
+                    .with_scale(Vec3::splat(2.0)),
+            )
+            .id();
+        let parent = world
+            .spawn((
+                Transform::from_xyz(1.0, 3.0, 0.5)
+                    .with_rotation(Quat::from_rotation_y(-0.7))
+                    .with_scale(Vec3::splat(0.5)),
+                policy,
+                ChildOf(grandparent),
+            ))
+            .id();
+        let children = [
+            world
+                .spawn((
+                    Transform::from_xyz(4.0, 0.0, -1.0).with_rotation(Quat::from_rotation_x(1.1)),
+                    ChildOf(parent),
+                ))
+                .id(),
+            world
+                .spawn((Transform::from_xyz(-2.0, 5.0, 0.0), ChildOf(parent)))
+                .id(),
+        ];
+        let grandchild = world
+            .spawn((Transform::from_xyz(0.0, 1.0, 0.0), ChildOf(children[0])))
+            .id();
+        // 先传播一次, 得到删除前的 GlobalTransform
+        app.update();
+        Scene {
+            grandparent,
+            parent,
+            children,
+            grandchild,
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((TransformPlugin, DespawnPolicyPlugin));
+        app
+    }
+
+    fn global(app: &App, entity: Entity) -> GlobalTransform {
+        *app.world().get::<GlobalTransform>(entity).unwrap()
+    }
+
+    // 删除父实体并再传播一次, 返回删除前子实体和孙实体的 GlobalTransform
+    fn despawn_parent(app: &mut App, scene: &Scene) -> Vec<GlobalTransform> {
+        let before = [scene.children[0], scene.children[1], scene.grandchild]
+            .map(|entity| global(app, entity))
+            .to_vec();
+        app.world_mut().despawn(scene.parent);
+        app.update();
+        assert!(app.world().get_entity(scene.parent).is_err());
+        before
+    }
+
+    fn assert_unchanged(app: &App, scene: &Scene, before: &[GlobalTransform]) {
+        for (entity, before) in [scene.children[0], scene.children[1], scene.grandchild]
+            .into_iter()
+            .zip(before)
+        {
+            let after = global(app, entity).affine();
+            assert!(
+                after.abs_diff_eq(before.affine(), 1e-4),
+                "{entity}: {before:?} -> {after:?}"
+            );
+        }
+        // 孙实体仍然挂在原来的父实体上
+        assert_eq!(
+            app.world()
+                .get::<ChildOf>(scene.grandchild)
+                .map(ChildOf::parent),
+            Some(scene.children[0])
+        );
+    }
+
+    #[test]
+    fn cascade_despawns_descendants() {
+        let mut app = app();
+        let scene = scene(&mut app, DespawnPolicy::Cascade);
+        despawn_parent(&mut app, &scene);
+        for entity in [scene.children[0], scene.children[1], scene.grandchild] {
+            assert!(app.world().get_entity(entity).is_err());
+        }
+        assert!(app.world().get::<Children>(scene.grandparent).is_none());
+    }
+
+    #[test]
+    fn reparent_moves_children_to_grandparent() {
+        let mut app = app();
+        let scene = scene(&mut app, DespawnPolicy::Reparent);
+        let before = despawn_parent(&mut app, &scene);
+        for child in scene.children {
+            assert_eq!(
+                app.world().get::<ChildOf>(child).map(ChildOf::parent),
+                Some(scene.grandparent)
+            );
+        }
+        assert_eq!(
+            app.world()
+                .get::<Children>(scene.grandparent)
+                .unwrap()
+                .to_vec(),
+            scene.children.to_vec()
+        );
+        assert_unchanged(&app, &scene, &before);
+    }
+
+    // 没有祖父实体时与 Detach 相同
+    #[test]
+    fn reparent_without_grandparent_detaches() {
+        let mut app = app();
+        let scene = scene(&mut app, DespawnPolicy::Reparent);
+        app.world_mut().entity_mut(scene.parent).remove::<ChildOf>();
+        app.update();
+        let before = despawn_parent(&mut app, &scene);
+        for child in scene.children {
+            assert!(app.world().get::<ChildOf>(child).is_none());
+        }
+        assert_unchanged(&app, &scene, &before);
+    }
+
+    #[test]
+    fn detach_makes_children_roots() {
+        let mut app = app();
+        let scene = scene(&mut app, DespawnPolicy::Detach);
+        let before = despawn_parent(&mut app, &scene);
+        for child in scene.children {
+            assert!(app.world().get::<ChildOf>(child).is_none());
+        }
+        assert!(app.world().get::<Children>(scene.grandparent).is_none());
+        assert_unchanged(&app, &scene, &before);
+    }
+}
@@ -0,0 +1,260 @@
+//! 通过反射打印实体上的所有组件
+//! 只有 derive(Reflect) 并 #[reflect(Component)] 注册过的组件才会被打印
+//!
+//! ```ignore
+//! // 普通 system
+//! fn print(query: Query<EntityRef>, registry: Res<AppTypeRegistry>) {
+//!     let printer = EntityPrinter::default().exclude::<Name>();
+//!     for entity in &query {
+//!         println!("{}", printer.format_entity_ref(entity, &registry.read()));
+//!     }
+//! }
+//! // 独占 system / 测试
+//! fn print_world(world: &mut World) {
+//!     let entity = world.spawn_empty().id();
+//!     println!("{}", EntityPrinter::json().format_entity(world, entity).unwrap());
+//! }
+//! ```
+
+use std::any::TypeId;
+
+use bevy::{
+    ecs::{entity::EntityDoesNotExistError, reflect::ReflectComponent},
+    platform::collections::HashSet,
+    prelude::*,
+    reflect::{TypeRegistry, serde::TypedReflectSerializer},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PrintFormat {
+    // Name { field: value } 形式,每个组件一行
+    #[default]
+    Pretty,
+    // {"entity": "..", "components": {"类型路径": 值}}
+    Json,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct EntityPrinter {
+    format: PrintFormat,
+    // 为 None 时打印所有组件
+    include: Option<HashSet<TypeId>>,
+    exclude: HashSet<TypeId>,
+}
+
+impl EntityPrinter {
+    pub fn json() -> Self {
+        Self {
+            format: PrintFormat::Json,
+            ..default()
+        }
+    }
+
+    pub fn with_format(mut self, format: PrintFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    // 只打印指定的组件,可以多次调用
+    pub fn include<T: Component>(mut self) -> Self {
+        self.include
+            .get_or_insert_with(HashSet::default)
+            .insert(TypeId::of::<T>());
+        self
+    }
+
+    // 不打印指定的组件
+    pub fn exclude<T: Component>(mut self) -> Self {
+        self.exclude.insert(TypeId::of::<T>());
+        self
+    }
+
+    fn accepts(&self, type_id: TypeId) -> bool {
+        !self.exclude.contains(&type_id)
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.contains(&type_id))
+    }
+
+    // 实体上的组件, 按短类型名排序,保证输出稳定
+    fn components<'w>(
+        &self,
+        entity: EntityRef<'w>,
+        registry: &TypeRegistry,
+    ) -> Vec<&'w dyn Reflect> {
+        let mut components: Vec<&dyn Reflect> = registry
+            .iter_with_data::<ReflectComponent>()
+            .filter(|(registration, _)| self.accepts(registration.type_id()))
+            .filter_map(|(_, reflect_component)| reflect_component.reflect(entity))
+            .collect();
+        components.sort_by_key(|component| component.reflect_short_type_path());
+        components
+    }
+
+    pub fn format_entity_ref(&self, entity: EntityRef, registry: &TypeRegistry) -> String {
+        let components = self.components(entity, registry);
+        match self.format {
+            PrintFormat::Pretty => {
+                let mut text = format!("entity {}", entity.id());
+                for component in components {
+                    text.push_str(&format!(
+                        "\n  {}: {component:?}",
+                        component.reflect_short_type_path()
+                    ));
+                }
+                text
+            }
+            PrintFormat::Json => {
+                let components = components
+                    .into_iter()
+                    .map(|component| {
+                        // 没有注册 Serialize 的类型退回到 Debug 文本
+                        let value = serde_json::to_value(TypedReflectSerializer::new(
+                            component.as_partial_reflect(),
+                            registry,
+                        ))
+                        .unwrap_or_else(|_| format!("{component:?}").into());
+                        (component.reflect_type_path().to_string(), value)
+                    })
+                    .collect::<serde_json::Map<_, _>>();
+                serde_json::json!({
+                    "entity": entity.id().to_string(),
+                    "components": components,
+                })
+                .to_string()
+            }
+        }
+    }
+
+    // 在独占 system 或测试中直接使用 World
+    pub fn format_entity(
+        &self,
+        world: &World,
+        entity: Entity,
+    ) -> Result<String, EntityDoesNotExistError> {
+        let entity = world.get_entity(entity)?;
+        let registry = world.resource::<AppTypeRegistry>().read();
+        Ok(self.format_entity_ref(entity, &registry))
+    }
+
+    pub fn print_entity_ref(&self, entity: EntityRef, registry: &TypeRegistry) {
+        println!("{}", self.format_entity_ref(entity, registry));
+    }
+
+    pub fn print_entity(
+        &self,
+        world: &World,
+        entity: Entity,
+    ) -> Result<(), EntityDoesNotExistError> {
+        println!("{}", self.format_entity(world, entity)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component, Reflect, Debug)]
+    #[reflect(Component)]
+    struct Health {
+        current: u32,
+        max: u32,
+    }
+
+    #[derive(Component, Reflect, Debug)]
+    #[reflect(Component)]
+    struct Armor(u32);
+
+    fn world() -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<AppTypeRegistry>();
+        {
+            let registry = world.resource::<AppTypeRegistry>();
+            let mut registry = registry.write();
+            registry.register::<Health>();
+            registry.register::<Armor>();
+            registry.register::<Name>();
+        }
+        let entity = world
+            .spawn((
+                Name::new("boss"),
+                Health {
+                    current: 30,
+                    max: 50,
+                },
+                Armor(5),
+            ))
+            .id();
+        (world, entity)
+    }
+
+    #[test]
+    fn pretty_lists_components_sorted_by_short_name() {
+        let (world, entity) = world();
+        let text = EntityPrinter::default()
+            .format_entity(&world, entity)
+            .unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], format!("entity {entity}"));
+        assert_eq!(lines.len(), 4, "{text}");
+        assert!(lines[1].starts_with("  Armor: "), "{text}");
+        assert!(lines[2].starts_with("  Health: "), "{text}");
+        assert!(lines[2].contains("30") && lines[2].contains("50"), "{text}");
+        assert!(lines[3].starts_with("  Name: "), "{text}");
+    }
+
+    #[test]
+    fn json_uses_type_paths_and_serialized_values() {
+        let (world, entity) = world();
+        let text = EntityPrinter::json().format_entity(&world, entity).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["entity"], entity.to_string());
+        let components = value["components"].as_object().unwrap();
+        assert_eq!(components.len(), 3, "{text}");
+        let health = &components[Health::type_path()];
+        assert_eq!(health["current"], 30);
+        assert_eq!(health["max"], 50);
+        assert_eq!(components[Armor::type_path()], 5);
+    }
+
+    #[test]
+    fn include_and_exclude_filter_components() {
+        let (world, entity) = world();
+        let only_health = EntityPrinter::default()
+            .include::<Health>()
+            .format_entity(&world, entity)
+            .unwrap();
+        assert_eq!(only_health.lines().count(), 2, "{only_health}");
+        assert!(only_health.contains("Health"));
+
+        let without_name = EntityPrinter::json()
+            .exclude::<Name>()
+            .format_entity(&world, entity)
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&without_name).unwrap();
+        let components = value["components"].as_object().unwrap();
+        assert_eq!(components.len(), 2);
+        assert!(!components.contains_key(Name::type_path()));
+
+        // exclude 优先于 include
+        let nothing = EntityPrinter::default()
+            .include::<Armor>()
+            .exclude::<Armor>()
+            .format_entity(&world, entity)
+            .unwrap();
+        assert_eq!(nothing, format!("entity {entity}"));
+    }
+
+    #[test]
+    fn missing_entity_is_an_error() {
+        let (mut world, entity) = world();
+        world.despawn(entity);
+        assert!(
+            EntityPrinter::default()
+                .format_entity(&world, entity)
+                .is_err()
+        );
+    }
+}
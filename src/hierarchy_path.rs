@@ -0,0 +1,378 @@
+//! 用 Name 组成的路径查找层次结构中的实体, 不依赖 Children 中的下标
+//! "root/arm/hand" 从根实体开始逐层按名字匹配, `*` 匹配一层任意名字, `**` 匹配零层或多层
+//! 没有 Name 的实体不能出现在路径中, `**` 也不会经过它们
+//!
+//! ```ignore
+//! fn grab(hierarchy: HierarchyPath) {
+//!     match hierarchy.resolve("root/arm/hand") {
+//!         Ok(hand) => info!("{}", hierarchy.path_of(hand).unwrap()),
+//!         // "root/arm" has no child named "hand" (children: elbow)
+//!         Err(err) => warn!("{err}"),
+//!     }
+//!     // 手下面任意深度的所有 finger
+//!     let fingers = hierarchy.resolve_all("root/**/finger");
+//! }
+//! ```
+
+use bevy::{ecs::system::SystemParam, platform::collections::HashSet, prelude::*};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HierarchyPathError {
+    #[error("invalid path \"{0}\": segments must not be empty")]
+    InvalidPath(String),
+    #[error("no root entity named \"{segment}\" (roots: {})", list(.available))]
+    RootNotFound {
+        segment: String,
+        available: Vec<String>,
+    },
+    #[error("\"{parent}\" has no child named \"{segment}\" (children: {})", list(.available))]
+    ChildNotFound {
+        parent: String,
+        segment: String,
+        available: Vec<String>,
+    },
+    #[error("path \"{path}\" matches {count} entities, expected exactly one")]
+    Ambiguous { path: String, count: usize },
+    #[error("entity {0} or one of its ancestors has no Name")]
+    Unnamed(Entity),
+}
+
+#[derive(SystemParam)]
+pub struct HierarchyPath<'w, 's> {
+    roots: Query<'w, 's, Entity, (With<Name>, Without<ChildOf>)>,
+    names: Query<'w, 's, &'static Name>,
+    children: Query<'w, 's, &'static Children>,
+    parents: Query<'w, 's, &'static ChildOf>,
+}
+
+impl HierarchyPath<'_, '_> {
+    // 路径必须正好匹配一个实体
+    pub fn resolve(&self, path: &str) -> Result<Entity, HierarchyPathError> {
+        match self.resolve_all(path)?.as_slice() {
+            [entity] => Ok(*entity),
+            matches => Err(HierarchyPathError::Ambiguous {
+                path: path.to_string(),
+                count: matches.len(),
+            }),
+        }
+    }
+
+    // 所有匹配的实体, 根实体按 Entity 排序, 子实体按 Children 中的顺序
+    // 某一层没有任何匹配时返回错误, 而不是空列表
+    pub fn resolve_all(&self, path: &str) -> Result<Vec<Entity>, HierarchyPathError> {
+        let segments: Vec<&str> = path.split('/').collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(HierarchyPathError::InvalidPath(path.to_string()));
+        }
+
+        // None 表示所有根实体之上的虚拟节点
+        let mut current: Vec<Option<Entity>> = vec![None];
+        for (depth, &segment) in segments.iter().enumerate() {
+            let next = match segment {
+                "**" => self.descendants(&current),
+                _ => current
+                    .iter()
+                    .flat_map(|&parent| self.named_children(parent))
+                    .filter(|&child| segment == "*" || self.name(child) == Some(segment))
+                    .map(Some)
+                    .collect(),
+            };
+            if next.is_empty() {
+                return Err(self.not_found(&segments[..depth], segment, &current));
+            }
+            current = dedup(next);
+        }
+        Ok(current.into_iter().flatten().collect())
+    }
+
+    // 从根实体开始的路径
+    pub fn path_of(&self, entity: Entity) -> Result<String, HierarchyPathError> {
+        let mut segments = Vec::new();
+        let mut next = Some(entity);
+        while let Some(current) = next {
+            let name = self
+                .name(current)
+                .ok_or(HierarchyPathError::Unnamed(entity))?;
+            segments.push(name);
+            next = self.parents.get(current).ok().map(ChildOf::parent);
+        }
+        segments.reverse();
+        Ok(segments.join("/"))
+    }
+
+    fn name(&self, entity: Entity) -> Option<&str> {
+        self.names.get(entity).ok().map(Name::as_str)
+    }
+
+    // parent 为 None 时返回所有有名字的根实体
+    fn named_children(&self, parent: Option<Entity>) -> Vec<Entity> {
+        match parent {
+            None => {
+                let mut roots: Vec<Entity> = self.roots.iter().collect();
+                roots.sort();
+                roots
+            }
+            Some(parent) => self
+                .children
+                .get(parent)
+                .map(|children| {
+                    children
+                        .iter()
+                        .filter(|&child| self.names.contains(child))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    // ** 匹配零层或多层: 自身加上所有有名字的后代, 深度优先
+    fn descendants(&self, current: &[Option<Entity>]) -> Vec<Option<Entity>> {
+        let mut result = Vec::new();
+        let mut stack: Vec<Option<Entity>> = current.iter().rev().copied().collect();
+        while let Some(node) = stack.pop() {
+            result.push(node);
+            stack.extend(self.named_children(node).into_iter().rev().map(Some));
+        }
+        result
+    }
+
+    fn not_found(
+        &self,
+        resolved: &[&str],
+        segment: &str,
+        current: &[Option<Entity>],
+    ) -> HierarchyPathError {
+        let available: Vec<String> = dedup(
+            current
+                .iter()
+                .flat_map(|&parent| self.named_children(parent))
+                .filter_map(|child| self.name(child).map(str::to_string))
+                .collect(),
+        );
+        if resolved.is_empty() {
+            HierarchyPathError::RootNotFound {
+                segment: segment.to_string(),
+                available,
+            }
+        } else {
+            HierarchyPathError::ChildNotFound {
+                parent: resolved.join("/"),
+                segment: segment.to_string(),
+                available,
+            }
+        }
+    }
+}
+
+// 错误信息中的名字列表
+fn list(names: &[String]) -> String {
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+// 去掉重复项, 保留第一次出现的顺序
+fn dedup<T: Clone + Eq + std::hash::Hash>(items: Vec<T>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    items
+        .into_iter()
+        .filter(|item| seen.insert(item.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    struct Tree {
+        root: Entity,
+        arm: Entity,
+        hand: Entity,
+        fingers: [Entity; 2],
+        leg: Entity,
+        toe: Entity,
+        hidden: Entity,
+        light: Entity,
+    }
+
+    // root ─┬─ arm ── elbow ── hand ─┬─ finger
+    //       │                        └─ finger
+    //       ├─ leg ── finger
+    //       └─ (没有名字) ── hidden
+    // light
+    fn tree(world: &mut World) -> Tree {
+        let root = world.spawn(Name::new("root")).id();
+        let mut child = |name: Option<&'static str>, parent: Entity| match name {
+            Some(name) => world.spawn((Name::new(name), ChildOf(parent))).id(),
+            None => world.spawn(ChildOf(parent)).id(),
+        };
+        let arm = child(Some("arm"), root);
+        let elbow = child(Some("elbow"), arm);
+        let hand = child(Some("hand"), elbow);
+        let fingers = [child(Some("finger"), hand), child(Some("finger"), hand)];
+        let leg = child(Some("leg"), root);
+        let toe = child(Some("finger"), leg);
+        let unnamed = child(None, root);
+        let hidden = child(Some("hidden"), unnamed);
+        let light = world.spawn(Name::new("light")).id();
+        Tree {
+            root,
+            arm,
+            hand,
+            fingers,
+            leg,
+            toe,
+            hidden,
+            light,
+        }
+    }
+
+    fn resolve_all(world: &mut World, path: &str) -> Result<Vec<Entity>, HierarchyPathError> {
+        let path = path.to_string();
+        world
+            .run_system_once(move |hierarchy: HierarchyPath| hierarchy.resolve_all(&path))
+            .unwrap()
+    }
+
+    fn resolve(world: &mut World, path: &str) -> Result<Entity, HierarchyPathError> {
+        let path = path.to_string();
+        world
+            .run_system_once(move |hierarchy: HierarchyPath| hierarchy.resolve(&path))
+            .unwrap()
+    }
+
+    fn path_of(world: &mut World, entity: Entity) -> Result<String, HierarchyPathError> {
+        world
+            .run_system_once(move |hierarchy: HierarchyPath| hierarchy.path_of(entity))
+            .unwrap()
+    }
+
+    #[test]
+    fn resolves_exact_paths() {
+        let mut world = World::new();
+        let tree = tree(&mut world);
+        assert_eq!(resolve(&mut world, "root"), Ok(tree.root));
+        assert_eq!(resolve(&mut world, "light"), Ok(tree.light));
+        assert_eq!(resolve(&mut world, "root/arm/elbow/hand"), Ok(tree.hand));
+        assert_eq!(resolve(&mut world, "root/leg/finger"), Ok(tree.toe));
+    }
+
+    #[test]
+    fn star_matches_one_level() {
+        let mut world = World::new();
+        let tree = tree(&mut world);
+        // 根实体按 Entity 排序, 没有名字的子实体被跳过
+        let mut roots = vec![tree.root, tree.light];
+        roots.sort();
+        assert_eq!(resolve_all(&mut world, "*"), Ok(roots));
+        assert_eq!(
+            resolve_all(&mut world, "root/*"),
+            Ok(vec![tree.arm, tree.leg])
+        );
+        assert_eq!(
+            resolve_all(&mut world, "root/*/*/hand/*"),
+            Ok(tree.fingers.to_vec())
+        );
+        assert_eq!(resolve_all(&mut world, "root/*/finger"), Ok(vec![tree.toe]));
+    }
+
+    #[test]
+    fn double_star_matches_zero_or_more_levels() {
+        let mut world = World::new();
+        let tree = tree(&mut world);
+        // 零层
+        assert_eq!(resolve(&mut world, "**/root"), Ok(tree.root));
+        assert_eq!(resolve(&mut world, "root/**/arm"), Ok(tree.arm));
+        // 多层, 深度优先
+        let fingers = vec![tree.fingers[0], tree.fingers[1], tree.toe];
+        assert_eq!(
+            resolve_all(&mut world, "root/**/finger"),
+            Ok(fingers.clone())
+        );
+        assert_eq!(resolve_all(&mut world, "**/finger"), Ok(fingers.clone()));
+        // 重叠的 ** 不会重复匹配同一个实体
+        assert_eq!(
+            resolve_all(&mut world, "root/**/**/finger"),
+            Ok(fingers.clone())
+        );
+        assert_eq!(resolve_all(&mut world, "**/*/**/finger"), Ok(fingers));
+        // ** 不经过没有名字的实体
+        assert!(resolve_all(&mut world, "root/**/hidden").is_err());
+    }
+
+    #[test]
+    fn path_of_round_trips() {
+        let mut world = World::new();
+        let tree = tree(&mut world);
+        for entity in [tree.root, tree.arm, tree.hand, tree.leg, tree.light] {
+            let path = path_of(&mut world, entity).unwrap();
+            assert_eq!(resolve(&mut world, &path), Ok(entity), "{path}");
+        }
+        assert_eq!(
+            path_of(&mut world, tree.hand),
+            Ok("root/arm/elbow/hand".to_string())
+        );
+        // 同名的兄弟路径相同, 只能用 resolve_all 找回
+        let path = path_of(&mut world, tree.fingers[1]).unwrap();
+        assert_eq!(resolve_all(&mut world, &path), Ok(tree.fingers.to_vec()));
+        assert_eq!(
+            path_of(&mut world, tree.hidden),
+            Err(HierarchyPathError::Unnamed(tree.hidden))
+        );
+    }
+
+    #[test]
+    fn errors_list_available_names() {
+        let mut world = World::new();
+        let tree = tree(&mut world);
+        let err = resolve(&mut world, "root/arm/hand").unwrap_err();
+        assert_eq!(
+            err,
+            HierarchyPathError::ChildNotFound {
+                parent: "root/arm".to_string(),
+                segment: "hand".to_string(),
+                available: vec!["elbow".to_string()],
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "\"root/arm\" has no child named \"hand\" (children: elbow)"
+        );
+        // 多个父实体的子实体合并去重
+        assert_eq!(
+            resolve(&mut world, "root/*/knee").unwrap_err().to_string(),
+            "\"root/*\" has no child named \"knee\" (children: elbow, finger)"
+        );
+        assert_eq!(
+            resolve(&mut world, "root/leg/finger/nail")
+                .unwrap_err()
+                .to_string(),
+            "\"root/leg/finger\" has no child named \"nail\" (children: none)"
+        );
+        let mut roots = [(tree.root, "root"), (tree.light, "light")];
+        roots.sort();
+        assert_eq!(
+            resolve(&mut world, "body").unwrap_err(),
+            HierarchyPathError::RootNotFound {
+                segment: "body".to_string(),
+                available: roots.map(|(_, name)| name.to_string()).to_vec(),
+            }
+        );
+        assert_eq!(
+            resolve(&mut world, "root//arm"),
+            Err(HierarchyPathError::InvalidPath("root//arm".to_string()))
+        );
+        assert_eq!(
+            resolve(&mut world, "root/**/finger"),
+            Err(HierarchyPathError::Ambiguous {
+                path: "root/**/finger".to_string(),
+                count: 3,
+            })
+        );
+    }
+}
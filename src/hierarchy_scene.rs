@@ -0,0 +1,359 @@
+//! 把一棵实体子树保存为场景文件, 再按相同的结构生成回来
+//! 保存 Name、Transform、Sprite 的颜色和图片路径, 其他组件会被忽略
+//! 文件中的实体用局部编号互相引用, 加载时为每个编号生成新的 Entity 并重新映射父子关系
+//!
+//! ```ignore
+//! fn save(world: &mut World) -> Result {
+//!     let scene = HierarchyScene::capture(world, root)?;
+//!     scene.save("assets/scenes/tree.ron")?;
+//!     Ok(())
+//! }
+//! fn load(mut commands: Commands, asset_server: Res<AssetServer>) -> Result {
+//!     let scene = HierarchyScene::load("assets/scenes/tree.ron")?;
+//!     let root = scene.spawn(&mut commands, &asset_server)?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum HierarchySceneError {
+    #[error("failed to access scene `{path}`: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid RON scene: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("failed to write RON scene: {0}")]
+    RonWrite(#[from] ron::Error),
+    #[error("entity {0} does not exist")]
+    NoSuchEntity(Entity),
+    #[error("scene is empty")]
+    Empty,
+    #[error("the first entity in a scene must be the root, but {0} has a parent")]
+    RootHasParent(u32),
+    #[error("entity id {0} is used more than once")]
+    DuplicateId(u32),
+    #[error("entity {0} has no parent, only the first entity may be a root")]
+    ExtraRoot(u32),
+    #[error("entity {id} refers to parent {parent}, which is not defined before it")]
+    UnknownParent { id: u32, parent: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneSprite {
+    // sRGBA
+    pub color: [f32; 4],
+    // 相对 assets 目录的路径, 没有图片时为 None
+    #[serde(default)]
+    pub image: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneEntity {
+    // 只在文件内有效的编号
+    pub id: u32,
+    #[serde(default)]
+    pub parent: Option<u32>,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    #[serde(default)]
+    pub sprite: Option<SceneSprite>,
+}
+
+// 父实体总在子实体之前, 第一个是根实体, 子实体按 Children 中的顺序排列
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HierarchyScene {
+    pub entities: Vec<SceneEntity>,
+}
+
+impl HierarchyScene {
+    // 深度优先收集 root 及其所有后代
+    pub fn capture(world: &World, root: Entity) -> Result<Self, HierarchySceneError> {
+        let mut entities = Vec::new();
+        let mut stack = vec![(root, None)];
+        while let Some((entity, parent)) = stack.pop() {
+            let entity_ref = world
+                .get_entity(entity)
+                .map_err(|_| HierarchySceneError::NoSuchEntity(entity))?;
+            let id = entities.len() as u32;
+            let transform = entity_ref.get::<Transform>().copied().unwrap_or_default();
+            entities.push(SceneEntity {
+                id,
+                parent,
+                name: entity_ref.get::<Name>().map(|name| name.to_string()),
+                translation: transform.translation,
+                rotation: transform.rotation,
+                scale: transform.scale,
+                sprite: entity_ref.get::<Sprite>().map(|sprite| SceneSprite {
+                    color: sprite.color.to_srgba().to_f32_array(),
+                    image: sprite.image.path().map(|path| path.to_string()),
+                }),
+            });
+            if let Some(children) = entity_ref.get::<Children>() {
+                stack.extend(children.iter().rev().map(|child| (child, Some(id))));
+            }
+        }
+        Ok(HierarchyScene { entities })
+    }
+
+    // 生成所有实体, 返回根实体
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+    ) -> Result<Entity, HierarchySceneError> {
+        self.validate()?;
+        let mut mapped: HashMap<u32, Entity> = HashMap::new();
+        for scene_entity in &self.entities {
+            let mut entity = commands.spawn(Transform {
+                translation: scene_entity.translation,
+                rotation: scene_entity.rotation,
+                scale: scene_entity.scale,
+            });
+            if let Some(name) = &scene_entity.name {
+                entity.insert(Name::new(name.clone()));
+            }
+            if let Some(sprite) = &scene_entity.sprite {
+                entity.insert(Sprite {
+                    image: sprite
+                        .image
+                        .as_ref()
+                        .map(|path| asset_server.load(path))
+                        .unwrap_or_default(),
+                    color: Srgba::from_f32_array(sprite.color).into(),
+                    ..default()
+                });
+            }
+            if let Some(parent) = scene_entity.parent {
+                entity.insert(ChildOf(mapped[&parent]));
+            }
+            mapped.insert(scene_entity.id, entity.id());
+        }
+        Ok(mapped[&self.entities[0].id])
+    }
+
+    // 检查编号和父子关系, 保证 spawn 时父实体已经生成
+    pub fn validate(&self) -> Result<(), HierarchySceneError> {
+        let Some(root) = self.entities.first() else {
+            return Err(HierarchySceneError::Empty);
+        };
+        if root.parent.is_some() {
+            return Err(HierarchySceneError::RootHasParent(root.id));
+        }
+        let mut defined = HashSet::new();
+        for (i, scene_entity) in self.entities.iter().enumerate() {
+            let id = scene_entity.id;
+            match scene_entity.parent {
+                None if i > 0 => return Err(HierarchySceneError::ExtraRoot(id)),
+                Some(parent) if !defined.contains(&parent) => {
+                    return Err(HierarchySceneError::UnknownParent { id, parent });
+                }
+                _ => {}
+            }
+            if !defined.insert(id) {
+                return Err(HierarchySceneError::DuplicateId(id));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, HierarchySceneError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|source| HierarchySceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_ron(&text)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), HierarchySceneError> {
+        let path = path.as_ref();
+        let text = self.to_ron()?;
+        let io_error = |source| HierarchySceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        std::fs::write(path, text).map_err(io_error)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, HierarchySceneError> {
+        Ok(ron::from_str(text)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String, HierarchySceneError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        asset::AssetPlugin,
+        color::palettes::css::{BLUE, LIME},
+    };
+
+    use super::*;
+
+    // 与 ch11_hierarchy 相同的树: root 下有 blue 和 lime, lime 下有 tip
+    fn spawn_tree(world: &mut World) -> Entity {
+        let image: Handle<Image> = world.resource::<AssetServer>().load("icon.png");
+        let sprite = |color: Srgba| Sprite {
+            image: image.clone(),
+            color: color.into(),
+            ..default()
+        };
+        world
+            .spawn((
+                Name::new("root"),
+                Sprite::from_image(image.clone()),
+                Transform::from_scale(Vec3::splat(0.75)).with_rotation(Quat::from_rotation_z(0.3)),
+            ))
+            .with_children(|root| {
+                root.spawn((
+                    Name::new("blue"),
+                    sprite(BLUE),
+                    Transform::from_xyz(250.0, 0.0, 0.0).with_scale(Vec3::splat(0.75)),
+                ));
+                root.spawn((
+                    Name::new("lime"),
+                    sprite(LIME),
+                    Transform::from_xyz(0.0, 250.0, 0.0).with_scale(Vec3::splat(0.75)),
+                ))
+                .with_child((
+                    Name::new("tip"),
+                    sprite(LIME),
+                    Transform::from_xyz(0.0, 200.0, 0.0).with_scale(Vec3::splat(0.5)),
+                ));
+            })
+            .id()
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Image>();
+        app
+    }
+
+    // 深度优先的 (名字, 父实体的名字, Transform, sRGBA 颜色, 图片路径)
+    type Node = (String, Option<String>, Transform, [f32; 4], Option<String>);
+
+    // 颜色在文件中保存为 sRGB, 线性颜色转换后可能差一个舍入误差
+    fn assert_same(actual: &[Node], expected: &[Node]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert_eq!(
+                (&actual.0, &actual.1, &actual.2, &actual.4),
+                (&expected.0, &expected.1, &expected.2, &expected.4)
+            );
+            for (a, b) in actual.3.iter().zip(expected.3) {
+                assert!(
+                    (a - b).abs() < 1e-6,
+                    "{}: {:?} vs {:?}",
+                    actual.0,
+                    actual.3,
+                    expected.3
+                );
+            }
+        }
+    }
+
+    fn nodes(world: &World, root: Entity) -> Vec<Node> {
+        let name = |entity: Entity| world.get::<Name>(entity).unwrap().to_string();
+        let mut nodes = Vec::new();
+        let mut stack = vec![root];
+        while let Some(entity) = stack.pop() {
+            let sprite = world.get::<Sprite>(entity).unwrap();
+            nodes.push((
+                name(entity),
+                world
+                    .get::<ChildOf>(entity)
+                    .map(|child_of| name(child_of.parent())),
+                *world.get::<Transform>(entity).unwrap(),
+                sprite.color.to_srgba().to_f32_array(),
+                sprite.image.path().map(|path| path.to_string()),
+            ));
+            if let Some(children) = world.get::<Children>(entity) {
+                stack.extend(children.iter().rev());
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    fn round_trip_through_ron() {
+        let mut app = app();
+        let world = app.world_mut();
+        let root = spawn_tree(world);
+        let original = nodes(world, root);
+
+        let scene = HierarchyScene::capture(world, root).unwrap();
+        let text = scene.to_ron().unwrap();
+        let loaded = HierarchyScene::from_ron(&text).unwrap();
+        assert_eq!(loaded, scene);
+
+        let asset_server = world.resource::<AssetServer>().clone();
+        let spawned = loaded.spawn(&mut world.commands(), &asset_server).unwrap();
+        world.flush();
+
+        assert_ne!(spawned, root);
+        let copy = nodes(world, spawned);
+        assert_same(&copy, &original);
+        assert_eq!(
+            copy.iter().map(|node| node.0.as_str()).collect::<Vec<_>>(),
+            ["root", "blue", "lime", "tip"]
+        );
+        assert!(
+            copy.iter()
+                .all(|node| node.4.as_deref() == Some("icon.png"))
+        );
+
+        // 生成的是一棵新树, ChildOf 指向新生成的实体, 原来的树不变
+        assert_eq!(world.query::<&Name>().iter(world).count(), 8);
+        let mut descendants = vec![spawned];
+        let mut index = 0;
+        while let Some(&entity) = descendants.get(index) {
+            descendants.extend(world.get::<Children>(entity).into_iter().flatten());
+            index += 1;
+        }
+        assert_eq!(descendants.len(), 4);
+        for entity in &descendants[1..] {
+            let parent = world.get::<ChildOf>(*entity).unwrap().parent();
+            assert!(descendants.contains(&parent), "{entity} points to {parent}");
+        }
+        assert_same(&nodes(world, root), &original);
+    }
+
+    #[test]
+    fn spawn_rejects_invalid_scenes() {
+        let mut app = app();
+        let world = app.world_mut();
+        let root = spawn_tree(world);
+        let mut scene = HierarchyScene::capture(world, root).unwrap();
+        scene.entities[2].parent = Some(42);
+        let asset_server = world.resource::<AssetServer>().clone();
+        assert!(matches!(
+            scene.spawn(&mut world.commands(), &asset_server),
+            Err(HierarchySceneError::UnknownParent { parent: 42, .. })
+        ));
+    }
+}
@@ -0,0 +1,15 @@
+//! 多个 example 共用的工具
+
+pub mod boids;
+pub mod bounds;
+pub mod camera_controller;
+pub mod despawn_policy;
+pub mod entity_printer;
+pub mod hierarchy_path;
+pub mod hierarchy_scene;
+pub mod nbody;
+pub mod pair_interaction;
+pub mod query_explain;
+pub mod sprite_motion;
+pub mod timeline;
+pub mod transform_animation;
@@ -0,0 +1,226 @@
+//! 积分器
+//! 从 ECS 中收集的天体状态在这里整体前进一步
+
+use bevy::prelude::*;
+
+use super::{GRAVITY_CONSTANT, solver::ForceSolver};
+
+// 积分器
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    // x' = 2x - last + a·dt², v' = (x' - x)/dt + ½a·dt, 每步一次引力计算
+    #[default]
+    PositionVerlet,
+    // x' = x + v·dt + ½a·dt², v' = v + ½(a + a')·dt, 每步两次引力计算
+    VelocityVerlet,
+    // v' = v + a·dt, x' = x + v'·dt, 每步一次引力计算
+    SymplecticEuler,
+    // 经典四阶龙格库塔, 不是辛积分器, 长时间运行能量会漂移, 每步四次引力计算
+    RungeKutta4,
+}
+
+impl Integrator {
+    pub fn next(self) -> Self {
+        match self {
+            Integrator::PositionVerlet => Integrator::VelocityVerlet,
+            Integrator::VelocityVerlet => Integrator::SymplecticEuler,
+            Integrator::SymplecticEuler => Integrator::RungeKutta4,
+            Integrator::RungeKutta4 => Integrator::PositionVerlet,
+        }
+    }
+
+    // 前进 dt, 需要时拆成多个子步, 返回子步数
+    // 第一个子步使用传入的 accelerations, 之后的子步用 solver 重新计算
+    pub fn advance(
+        self,
+        bodies: &mut Bodies,
+        accelerations: &[Vec3],
+        dt: f32,
+        solver: &ForceSolver,
+        substepping: &Substepping,
+    ) -> u32 {
+        let substeps = substepping.substeps(bodies, dt, solver.softening);
+        if substeps == 1 {
+            self.step(bodies, accelerations, dt, solver);
+            return 1;
+        }
+
+        // 位置 Verlet 的 LastPos 间隔必须等于步长, 子步前后各换算一次
+        // 换算回 dt 需要最终位置的加速度, 多算一次引力
+        let h = dt / substeps as f32;
+        let position_verlet = self == Integrator::PositionVerlet;
+        if position_verlet {
+            rescale_last_positions(bodies, accelerations, dt, h);
+        }
+        self.step(bodies, accelerations, h, solver);
+        for _ in 1..substeps {
+            let accelerations = solver.accelerations(&bodies.positions, &bodies.masses);
+            self.step(bodies, &accelerations, h, solver);
+        }
+        if position_verlet {
+            let accelerations = solver.accelerations(&bodies.positions, &bodies.masses);
+            rescale_last_positions(bodies, &accelerations, h, dt);
+        } else {
+            bodies.last_positions = axpy(&bodies.positions, &bodies.velocities, -dt);
+        }
+        substeps
+    }
+
+    // 前进一步, accelerations 是当前位置的加速度
+    // 其他阶段需要的加速度用同一个 solver 重新计算
+    pub fn step(self, bodies: &mut Bodies, accelerations: &[Vec3], dt: f32, solver: &ForceSolver) {
+        let x0 = bodies.positions.clone();
+        let v0 = bodies.velocities.clone();
+        match self {
+            Integrator::PositionVerlet => {
+                // (x' - x) / dt 是半步处的速度, 再加上半步的加速度得到新位置处的速度
+                for i in 0..x0.len() {
+                    bodies.positions[i] =
+                        x0[i] * 2.0 - bodies.last_positions[i] + accelerations[i] * dt * dt;
+                    bodies.velocities[i] =
+                        (bodies.positions[i] - x0[i]) / dt + accelerations[i] * (0.5 * dt);
+                }
+            }
+            Integrator::VelocityVerlet => {
+                for i in 0..x0.len() {
+                    bodies.positions[i] = x0[i] + v0[i] * dt + accelerations[i] * (0.5 * dt * dt);
+                }
+                let next = solver.accelerations(&bodies.positions, &bodies.masses);
+                for i in 0..x0.len() {
+                    bodies.velocities[i] = v0[i] + (accelerations[i] + next[i]) * (0.5 * dt);
+                }
+            }
+            Integrator::SymplecticEuler => {
+                for i in 0..x0.len() {
+                    bodies.velocities[i] = v0[i] + accelerations[i] * dt;
+                    bodies.positions[i] = x0[i] + bodies.velocities[i] * dt;
+                }
+            }
+            Integrator::RungeKutta4 => {
+                // k?x 是位置的导数(速度), k?v 是速度的导数(加速度)
+                let k1x = v0.clone();
+                let k1v = accelerations.to_vec();
+                let k2x = axpy(&v0, &k1v, dt / 2.0);
+                let k2v = solver.accelerations(&axpy(&x0, &k1x, dt / 2.0), &bodies.masses);
+                let k3x = axpy(&v0, &k2v, dt / 2.0);
+                let k3v = solver.accelerations(&axpy(&x0, &k2x, dt / 2.0), &bodies.masses);
+                let k4x = axpy(&v0, &k3v, dt);
+                let k4v = solver.accelerations(&axpy(&x0, &k3x, dt), &bodies.masses);
+                for i in 0..x0.len() {
+                    bodies.positions[i] =
+                        x0[i] + (k1x[i] + k2x[i] * 2.0 + k3x[i] * 2.0 + k4x[i]) * (dt / 6.0);
+                    bodies.velocities[i] =
+                        v0[i] + (k1v[i] + k2v[i] * 2.0 + k3v[i] * 2.0 + k4v[i]) * (dt / 6.0);
+                }
+            }
+        }
+
+        // 同步 LastPos, 切换回位置 Verlet 时速度保持不变
+        bodies.last_positions = match self {
+            Integrator::PositionVerlet => x0,
+            _ => axpy(&bodies.positions, &bodies.velocities, -dt),
+        };
+    }
+}
+
+// 近距离交会时把一个固定步拆成多个子步
+// 子步长 ≤ accuracy · 最小的 τ, τ = 距离 / (相对速度 + √(G·(m1 + m2))), 即两者相遇需要的时间
+// 寻找最小的 τ 需要两两检查 O(n²), 默认关闭, 天体较少或需要精确交会时再开启
+#[derive(Resource, Debug, Clone)]
+pub struct Substepping {
+    pub enabled: bool,
+    pub accuracy: f32,
+    pub max_substeps: u32,
+}
+
+impl Default for Substepping {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            accuracy: 0.05,
+            max_substeps: 64,
+        }
+    }
+}
+
+impl Substepping {
+    // 两两检查所有天体, 距离按 softening 软化
+    pub fn substeps(&self, bodies: &Bodies, dt: f32, softening: f32) -> u32 {
+        if !self.enabled {
+            return 1;
+        }
+        let mut min_timescale = f32::INFINITY;
+        let n = bodies.positions.len();
+        for i in 0..n {
+            for j in i + 1..n {
+                let distance = ops::sqrt(
+                    bodies.positions[i].distance_squared(bodies.positions[j])
+                        + softening * softening,
+                );
+                let speed = bodies.velocities[i].distance(bodies.velocities[j])
+                    + ops::sqrt(GRAVITY_CONSTANT * (bodies.masses[i] + bodies.masses[j]));
+                min_timescale = min_timescale.min(distance / speed);
+            }
+        }
+        let substeps = (dt / (self.accuracy * min_timescale)).ceil();
+        if substeps.is_finite() {
+            (substeps as u32).clamp(1, self.max_substeps.max(1))
+        } else {
+            self.max_substeps.max(1)
+        }
+    }
+}
+
+// 把 LastPos 的间隔从 from 换算为 to, 使位置 Verlet 下一步等价于变步长 Verlet
+// x' = x + (x - last)·to/from + a·to·(to + from)/2 = 2x - last' + a·to²
+fn rescale_last_positions(bodies: &mut Bodies, accelerations: &[Vec3], from: f32, to: f32) {
+    for ((last, position), acceleration) in bodies
+        .last_positions
+        .iter_mut()
+        .zip(&bodies.positions)
+        .zip(accelerations)
+    {
+        *last = *position - (*position - *last) * (to / from)
+            + *acceleration * (to * (to - from) / 2.0);
+    }
+}
+
+// base + delta * scale
+pub fn axpy(base: &[Vec3], delta: &[Vec3], scale: f32) -> Vec<Vec3> {
+    base.iter()
+        .zip(delta)
+        .map(|(base, delta)| *base + *delta * scale)
+        .collect()
+}
+
+// 积分时从 ECS 中收集的天体状态, 下标一一对应
+#[derive(Debug, Clone, Default)]
+pub struct Bodies {
+    pub masses: Vec<f32>,
+    pub positions: Vec<Vec3>,
+    pub last_positions: Vec<Vec3>,
+    pub velocities: Vec<Vec3>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 匀加速运动时位置 Verlet 的位置是精确的, 速度也应该是新位置处的精确速度
+    #[test]
+    fn position_verlet_velocity_is_at_new_position() {
+        let (dt, v0, a) = (0.1, Vec3::new(1.0, 2.0, 0.0), Vec3::new(0.0, -10.0, 0.0));
+        let mut bodies = Bodies {
+            masses: vec![1.0],
+            positions: vec![Vec3::ZERO],
+            last_positions: vec![-v0 * dt + a * (0.5 * dt * dt)],
+            velocities: vec![v0],
+        };
+        for step in 1..=10 {
+            Integrator::PositionVerlet.step(&mut bodies, &[a], dt, &ForceSolver::default());
+            let t = step as f32 * dt;
+            assert!(bodies.positions[0].abs_diff_eq(v0 * t + a * (0.5 * t * t), 1e-4));
+            assert!(bodies.velocities[0].abs_diff_eq(v0 + a * t, 1e-4));
+        }
+    }
+}
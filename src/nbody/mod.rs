@@ -0,0 +1,416 @@
+//! n-body 引力模拟核心, 不依赖渲染, 可以在 MinimalPlugins 下运行
+//! 渲染部分见 [`render::NBodyRenderPlugin`]
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins((MinimalPlugins, NBodyPlugin))
+//!     .add_systems(Startup, generate_bodies)
+//!     .run();
+//! ```
+
+mod integrator;
+mod octree;
+mod prediction;
+pub mod render;
+mod scenario;
+mod solver;
+mod trajectory;
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    math::FloatPow,
+    platform::collections::HashSet,
+    prelude::*,
+};
+
+pub use integrator::{Bodies, Integrator, Substepping, axpy};
+pub use octree::Octree;
+pub use prediction::{GhostSimulation, OrbitPrediction};
+pub use scenario::{BodySpec, Preset, Scenario, ScenarioError, ScenarioFormat};
+pub use solver::{ForceSolver, SolverKind, force_unit_mass, solver_is};
+pub use trajectory::{Frame, Sample, TrajectoryError, TrajectoryRecorder};
+
+pub const GRAVITY_CONSTANT: f32 = 0.001; // 重力常数
+pub const NUM_BODIES: usize = 100; // 球体数量
+
+pub const KINETIC_ENERGY: DiagnosticPath = DiagnosticPath::const_new("nbody/kinetic_energy");
+pub const POTENTIAL_ENERGY: DiagnosticPath = DiagnosticPath::const_new("nbody/potential_energy");
+pub const TOTAL_ENERGY: DiagnosticPath = DiagnosticPath::const_new("nbody/total_energy");
+pub const MOMENTUM: DiagnosticPath = DiagnosticPath::const_new("nbody/momentum");
+// 每帧所有固定步的子步数之和, 没有执行固定步的帧为 0
+pub const SUBSTEPS: DiagnosticPath = DiagnosticPath::const_new("nbody/substeps");
+
+#[derive(Component, Default)]
+pub struct Mass(pub f32); // 质量
+
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct Acceleration(pub Vec3); // 加速度
+
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct LastPos(pub Vec3);
+
+// 速度, 位置 Verlet 以外的积分器使用
+// 位置 Verlet 用 LastPos 隐式表示速度, 每步积分后两者都会更新, 可以随时切换积分器
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct Velocity(pub Vec3);
+
+// 天体颜色, 只是数据, 由渲染插件使用
+#[derive(Component, Clone, Copy, Deref, DerefMut)]
+pub struct BodyColor(pub Color);
+
+#[derive(Component)]
+pub struct Star;
+
+// 天体的物理组件, 半径使用 Transform::scale
+#[derive(Bundle, Default)]
+pub struct BodyBundle {
+    pub mass: Mass,
+    pub last_pos: LastPos,
+    pub velocity: Velocity,
+    pub acceleration: Acceleration,
+}
+
+// 插入这个资源后 NBodyPlugin 每步发布动能、势能、动量诊断
+// 势能需要两两计算 O(n²), 天体很多时会比 Barnes–Hut 求解本身还慢
+#[derive(Resource, Debug, Default)]
+pub struct EnergyDiagnostics;
+
+// 每个 FixedUpdate 中按顺序执行
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NBodySystems {
+    // 计算加速度
+    Forces,
+    // 积分
+    Integrate,
+    // 碰撞合并
+    Merge,
+    // 发布诊断, 只在存在 EnergyDiagnostics 时运行
+    Diagnostics,
+    // 记录轨迹, 只在存在 TrajectoryRecorder 时运行
+    Record,
+    // 轨道预测, 只在存在 OrbitPrediction 时运行
+    Predict,
+}
+
+// 物理计算全部使用 Transform, 天体都是根实体, 不需要等 GlobalTransform 传播
+pub struct NBodyPlugin;
+
+impl Plugin for NBodyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ForceSolver>()
+            .init_resource::<Scenario>()
+            .init_resource::<Integrator>()
+            .init_resource::<Substepping>()
+            .init_resource::<FrameSubsteps>()
+            .register_diagnostic(Diagnostic::new(KINETIC_ENERGY))
+            .register_diagnostic(Diagnostic::new(POTENTIAL_ENERGY))
+            .register_diagnostic(Diagnostic::new(TOTAL_ENERGY))
+            .register_diagnostic(Diagnostic::new(MOMENTUM))
+            .register_diagnostic(Diagnostic::new(SUBSTEPS))
+            .configure_sets(
+                FixedUpdate,
+                (
+                    NBodySystems::Forces,
+                    NBodySystems::Integrate,
+                    NBodySystems::Merge,
+                    NBodySystems::Diagnostics,
+                    NBodySystems::Record,
+                    NBodySystems::Predict,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        solver::interact_bodies.run_if(solver_is(SolverKind::Exact)),
+                        solver::parallel_interact_bodies.run_if(solver_is(SolverKind::Parallel)),
+                        solver::barnes_hut_bodies.run_if(solver_is(SolverKind::BarnesHut)),
+                    )
+                        .in_set(NBodySystems::Forces),
+                    integrate.in_set(NBodySystems::Integrate),
+                    merge_bodies.in_set(NBodySystems::Merge),
+                    energy_diagnostics
+                        .run_if(resource_exists::<EnergyDiagnostics>)
+                        .in_set(NBodySystems::Diagnostics),
+                    trajectory::record_trajectories
+                        .run_if(resource_exists::<TrajectoryRecorder>)
+                        .in_set(NBodySystems::Record),
+                    prediction::update_prediction
+                        .run_if(resource_exists::<OrbitPrediction>)
+                        .in_set(NBodySystems::Predict),
+                ),
+            )
+            .add_systems(Last, publish_substeps);
+    }
+}
+
+// 根据 Scenario 资源生成天体
+pub fn generate_bodies(mut commands: Commands, scenario: Res<Scenario>, time: Res<Time<Fixed>>) {
+    scenario.spawn(&mut commands, time.timestep().as_secs_f32());
+}
+
+// 用于合并计算的天体状态
+// 半径即 Transform::scale (球体网格半径为 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BodyState {
+    mass: f32,
+    position: Vec3,
+    last_pos: Vec3,
+    velocity: Vec3,
+    radius: f32,
+}
+
+impl BodyState {
+    fn collides(&self, other: &BodyState) -> bool {
+        self.position.distance_squared(other.position) < (self.radius + other.radius).squared()
+    }
+
+    // 完全非弹性碰撞
+    // 速度 = (position - last_pos) / dt 是线性的, 所以按质量加权平均 position 和 last_pos
+    // 就等于按质量加权平均速度, 动量守恒且不需要 dt, Velocity 同样按质量加权平均
+    // 体积守恒, 半径 = ∛(r1³ + r2³)
+    fn merge(&self, other: &BodyState) -> BodyState {
+        let mass = self.mass + other.mass;
+        BodyState {
+            mass,
+            position: (self.position * self.mass + other.position * other.mass) / mass,
+            last_pos: (self.last_pos * self.mass + other.last_pos * other.mass) / mass,
+            velocity: (self.velocity * self.mass + other.velocity * other.mass) / mass,
+            radius: ops::cbrt(self.radius.cubed() + other.radius.cubed()),
+        }
+    }
+}
+
+// 相交的天体合并到较重的一个上, 较轻的被删除
+// 每一步中一个天体只参与一次合并, 剩下的重叠留到下一步处理
+fn merge_bodies(
+    mut commands: Commands,
+    mut query: Query<(
+        Entity,
+        &mut Mass,
+        &mut Transform,
+        &mut LastPos,
+        &mut Velocity,
+    )>,
+) {
+    let state =
+        |mass: &Mass, transform: &Transform, last_pos: &LastPos, velocity: &Velocity| BodyState {
+            mass: mass.0,
+            position: transform.translation,
+            last_pos: last_pos.0,
+            velocity: velocity.0,
+            radius: transform.scale.x,
+        };
+
+    let mut collisions = Vec::new();
+    for [
+        (entity1, mass1, transform1, last_pos1, velocity1),
+        (entity2, mass2, transform2, last_pos2, velocity2),
+    ] in query.iter_combinations()
+    {
+        let body1 = state(mass1, transform1, last_pos1, velocity1);
+        let body2 = state(mass2, transform2, last_pos2, velocity2);
+        if body1.collides(&body2) {
+            collisions.push(if body1.mass >= body2.mass {
+                (entity1, entity2)
+            } else {
+                (entity2, entity1)
+            });
+        }
+    }
+
+    let mut merged = HashSet::new();
+    for (survivor, absorbed) in collisions {
+        if merged.contains(&survivor) || merged.contains(&absorbed) {
+            continue;
+        }
+        let Ok(
+            [
+                (_, mut mass, mut transform, mut last_pos, mut velocity),
+                (_, absorbed_mass, absorbed_transform, absorbed_last_pos, absorbed_velocity),
+            ],
+        ) = query.get_many_mut([survivor, absorbed])
+        else {
+            continue;
+        };
+        let body = state(&mass, &transform, &last_pos, &velocity).merge(&state(
+            &absorbed_mass,
+            &absorbed_transform,
+            &absorbed_last_pos,
+            &absorbed_velocity,
+        ));
+
+        mass.0 = body.mass;
+        transform.translation = body.position;
+        transform.scale = Vec3::splat(body.radius);
+        last_pos.0 = body.last_pos;
+        velocity.0 = body.velocity;
+
+        merged.insert(survivor);
+        merged.insert(absorbed);
+        commands.entity(absorbed).despawn();
+    }
+}
+
+// 本帧的固定步已经执行的子步数
+#[derive(Resource, Default)]
+struct FrameSubsteps(u32);
+
+// 收集所有天体的状态, 交给积分器整体计算后写回, 累计这一步的子步数
+fn integrate(
+    time: Res<Time>,
+    integrator: Res<Integrator>,
+    solver: Res<ForceSolver>,
+    substepping: Res<Substepping>,
+    mut frame_substeps: ResMut<FrameSubsteps>,
+    mut query: Query<(
+        &Mass,
+        &mut Acceleration,
+        &mut Transform,
+        &mut LastPos,
+        &mut Velocity,
+    )>,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+
+    let mut bodies = Bodies::default();
+    let mut accelerations = Vec::new();
+    for (mass, acceleration, transform, last_pos, velocity) in &query {
+        bodies.masses.push(mass.0);
+        bodies.positions.push(transform.translation);
+        bodies.last_positions.push(last_pos.0);
+        bodies.velocities.push(velocity.0);
+        accelerations.push(acceleration.0);
+    }
+
+    frame_substeps.0 += integrator.advance(&mut bodies, &accelerations, dt, &solver, &substepping);
+
+    for (i, (_, mut acceleration, mut transform, mut last_pos, mut velocity)) in
+        query.iter_mut().enumerate()
+    {
+        **acceleration = Vec3::ZERO;
+        transform.translation = bodies.positions[i];
+        **last_pos = bodies.last_positions[i];
+        **velocity = bodies.velocities[i];
+    }
+}
+
+// 一帧中 FixedUpdate 可能执行 0 次或多次, 诊断按帧发布, 与其他按帧的诊断可以直接比较
+fn publish_substeps(mut diagnostics: Diagnostics, mut frame_substeps: ResMut<FrameSubsteps>) {
+    let substeps = std::mem::take(&mut frame_substeps.0);
+    diagnostics.add_measurement(&SUBSTEPS, || substeps as f64);
+}
+
+// 引力大小为 G·m1·m2/r (force_unit_mass 中 delta 没有归一化), 对应的势能为 G·m1·m2·ln(r)
+// 软化后为 G·m1·m2·r/(r² + ε²), 势能为 G·m1·m2·½ln(r² + ε²)
+fn energy_diagnostics(
+    mut diagnostics: Diagnostics,
+    solver: Res<ForceSolver>,
+    query: Query<(&Mass, &Transform, &Velocity)>,
+) {
+    let mut kinetic = 0.0;
+    let mut momentum = Vec3::ZERO;
+    for (Mass(mass), _, velocity) in &query {
+        kinetic += 0.5 * mass * velocity.length_squared();
+        momentum += velocity.0 * *mass;
+    }
+
+    let mut potential = 0.0;
+    for [(Mass(m1), transform1, _), (Mass(m2), transform2, _)] in query.iter_combinations() {
+        let distance_sq = transform1
+            .translation
+            .distance_squared(transform2.translation)
+            + solver.softening * solver.softening;
+        potential += GRAVITY_CONSTANT * m1 * m2 * 0.5 * ops::ln(distance_sq);
+    }
+
+    diagnostics.add_measurement(&KINETIC_ENERGY, || kinetic as f64);
+    diagnostics.add_measurement(&POTENTIAL_ENERGY, || potential as f64);
+    diagnostics.add_measurement(&TOTAL_ENERGY, || (kinetic + potential) as f64);
+    diagnostics.add_measurement(&MOMENTUM, || momentum.length() as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn spawn_body(world: &mut World, mass: f32, position: Vec3, velocity: Vec3) -> Entity {
+        world
+            .spawn((
+                BodyBundle {
+                    mass: Mass(mass),
+                    last_pos: LastPos(position - velocity * 0.1),
+                    velocity: Velocity(velocity),
+                    ..default()
+                },
+                Transform::from_translation(position),
+            ))
+            .id()
+    }
+
+    // (总质量, Σm·v, 由 LastPos 得到的 Σm·(x - last), 天体数量)
+    fn totals(world: &mut World) -> (f32, Vec3, Vec3, usize) {
+        let mut query = world.query::<(&Mass, &Transform, &LastPos, &Velocity)>();
+        query.iter(world).fold(
+            (0.0, Vec3::ZERO, Vec3::ZERO, 0),
+            |(mass, momentum, displacement, count), (m, transform, last_pos, velocity)| {
+                (
+                    mass + m.0,
+                    momentum + velocity.0 * m.0,
+                    displacement + (transform.translation - last_pos.0) * m.0,
+                    count + 1,
+                )
+            },
+        )
+    }
+
+    #[test]
+    fn merge_conserves_mass_and_momentum() {
+        let mut world = World::new();
+        // a 与 b、b 与 c 重叠, 一步中 b 只能合并一次
+        let a = spawn_body(&mut world, 1.0, Vec3::ZERO, Vec3::X);
+        let b = spawn_body(&mut world, 5.0, Vec3::new(1.5, 0.0, 0.0), Vec3::Y * 2.0);
+        let c = spawn_body(&mut world, 2.0, Vec3::new(3.0, 0.0, 0.0), Vec3::NEG_X);
+        // 另一对重叠的天体, 轻的 e 被合并
+        let d = spawn_body(&mut world, 3.0, Vec3::splat(20.0), Vec3::Z);
+        let e = spawn_body(
+            &mut world,
+            1.0,
+            Vec3::new(21.0, 20.0, 20.0),
+            Vec3::NEG_Z * 3.0,
+        );
+        // 不与任何天体重叠
+        let f = spawn_body(&mut world, 4.0, Vec3::splat(-20.0), Vec3::ONE);
+
+        let (mass, momentum, displacement, count) = totals(&mut world);
+        assert_eq!(count, 6);
+        world.run_system_once(merge_bodies).unwrap();
+        let (merged_mass, merged_momentum, merged_displacement, merged_count) = totals(&mut world);
+
+        assert_eq!(merged_count, 4);
+        assert!(world.get_entity(b).is_ok() && world.get_entity(d).is_ok());
+        assert!(world.get_entity(e).is_err() && world.get_entity(f).is_ok());
+        assert!(world.get_entity(a).is_err() != world.get_entity(c).is_err());
+        assert!((merged_mass - mass).abs() < 1e-5);
+        assert!(merged_momentum.abs_diff_eq(momentum, 1e-5));
+        assert!(merged_displacement.abs_diff_eq(displacement, 1e-5));
+
+        // 合并后的天体体积守恒
+        assert_eq!(world.get::<Mass>(d).unwrap().0, 4.0);
+        let radius = world.get::<Transform>(d).unwrap().scale.x;
+        assert!((radius - ops::cbrt(2.0)).abs() < 1e-5);
+
+        // 剩下的重叠在下一步合并
+        world.run_system_once(merge_bodies).unwrap();
+        let (final_mass, final_momentum, _, final_count) = totals(&mut world);
+        assert_eq!(final_count, 3);
+        assert!((final_mass - mass).abs() < 1e-5);
+        assert!(final_momentum.abs_diff_eq(momentum, 1e-5));
+    }
+}
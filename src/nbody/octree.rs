@@ -0,0 +1,263 @@
+//! Barnes–Hut 八叉树
+//! 把远处的一团天体看作一个位于质心的质点, 引力计算从 O(n²) 降到 O(n log n)
+
+use bevy::{math::FloatPow, prelude::*};
+
+use super::solver::force_unit_mass;
+
+// 八叉树节点
+// 叶子节点最多保存一个天体, 内部节点保存所有子节点的总质量和质心
+#[derive(Debug, Clone)]
+pub struct OctreeNode {
+    pub center: Vec3,
+    pub half_size: f32,
+    pub mass: f32,
+    // 先累加 质量*位置, 建树完成后除以质量得到质心
+    pub center_of_mass: Vec3,
+    // 8 个子节点在 nodes 中连续存放, 这里是第一个的下标
+    pub children: Option<usize>,
+    pub body: Option<usize>,
+}
+
+impl OctreeNode {
+    fn new(center: Vec3, half_size: f32) -> Self {
+        Self {
+            center,
+            half_size,
+            mass: 0.0,
+            center_of_mass: Vec3::ZERO,
+            children: None,
+            body: None,
+        }
+    }
+
+    // 位置所在的子节点序号 (0..8)
+    fn octant(&self, position: Vec3) -> usize {
+        (position.x >= self.center.x) as usize
+            | ((position.y >= self.center.y) as usize) << 1
+            | ((position.z >= self.center.z) as usize) << 2
+    }
+
+    // 包含边界, 正好落在边界上的天体也算在内
+    fn contains(&self, position: Vec3) -> bool {
+        (position - self.center).abs().max_element() <= self.half_size
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Octree {
+    pub nodes: Vec<OctreeNode>,
+}
+
+impl Octree {
+    // 重合的天体会无限细分, 超过这个深度后直接合并到同一个叶子
+    const MAX_DEPTH: usize = 32;
+
+    pub fn build(bodies: &[(Vec3, f32)]) -> Self {
+        let mut octree = Octree::default();
+        let Some(bounds) = bodies
+            .iter()
+            .map(|(position, _)| (*position, *position))
+            .reduce(|(min, max), (position, _)| (min.min(position), max.max(position)))
+        else {
+            return octree;
+        };
+        let half_size = ((bounds.1 - bounds.0).max_element() / 2.0).max(f32::EPSILON);
+        octree
+            .nodes
+            .push(OctreeNode::new((bounds.0 + bounds.1) / 2.0, half_size));
+
+        for index in 0..bodies.len() {
+            octree.insert(0, index, bodies, 0);
+        }
+        for node in &mut octree.nodes {
+            match node.body {
+                // 直接使用天体位置, 除法的舍入误差会让天体受到自身的引力
+                Some(index) if node.mass == bodies[index].1 => {
+                    node.center_of_mass = bodies[index].0
+                }
+                _ if node.mass > 0.0 => node.center_of_mass /= node.mass,
+                _ => {}
+            }
+        }
+        octree
+    }
+
+    fn insert(&mut self, node: usize, index: usize, bodies: &[(Vec3, f32)], depth: usize) {
+        let (position, mass) = bodies[index];
+        let current = &mut self.nodes[node];
+        current.mass += mass;
+        current.center_of_mass += position * mass;
+
+        if let Some(first_child) = current.children {
+            let child = first_child + current.octant(position);
+            return self.insert(child, index, bodies, depth + 1);
+        }
+
+        let Some(existing) = current.body else {
+            current.body = Some(index);
+            return;
+        };
+        if depth >= Self::MAX_DEPTH {
+            return;
+        }
+
+        // 叶子已经有天体, 细分后把两个天体都放进子节点
+        let (center, half_size) = (current.center, current.half_size / 2.0);
+        let first_child = self.nodes.len();
+        self.nodes.extend((0..8).map(|octant| {
+            let offset = Vec3::new(
+                if octant & 1 != 0 {
+                    half_size
+                } else {
+                    -half_size
+                },
+                if octant & 2 != 0 {
+                    half_size
+                } else {
+                    -half_size
+                },
+                if octant & 4 != 0 {
+                    half_size
+                } else {
+                    -half_size
+                },
+            );
+            OctreeNode::new(center + offset, half_size)
+        }));
+        let current = &mut self.nodes[node];
+        current.children = Some(first_child);
+        current.body = None;
+
+        // 已有天体的质量在上面已经计入本节点, 这里只需要放进子节点
+        let (existing_position, existing_mass) = bodies[existing];
+        let child = first_child + current.octant(existing_position);
+        self.nodes[child].body = Some(existing);
+        self.nodes[child].mass += existing_mass;
+        self.nodes[child].center_of_mass += existing_position * existing_mass;
+
+        let child = first_child + self.nodes[node].octant(position);
+        self.insert(child, index, bodies, depth + 1);
+    }
+
+    // softening 见 force_unit_mass
+    pub fn acceleration(&self, position: Vec3, theta: f32, softening: f32) -> Vec3 {
+        let mut acceleration = Vec3::ZERO;
+        if self.nodes.is_empty() {
+            return acceleration;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.mass <= 0.0 {
+                continue;
+            }
+            let delta = node.center_of_mass - position;
+            let distance_sq = delta.length_squared();
+            match node.children {
+                // 足够远, 整个节点看作一个质点
+                // 包含自身的节点一定展开, 否则 θ 较大时天体会受到自身质量的引力
+                Some(_)
+                    if !node.contains(position)
+                        && (node.half_size * 2.0).squared() < theta.squared() * distance_sq =>
+                {
+                    acceleration += force_unit_mass(delta, softening) * node.mass;
+                }
+                Some(first_child) => stack.extend(first_child..first_child + 8),
+                // 叶子, 跳过自身
+                None if distance_sq > 0.0 => {
+                    acceleration += force_unit_mass(delta, softening) * node.mass
+                }
+                None => {}
+            }
+        }
+        acceleration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::nbody::{ForceSolver, SolverKind};
+
+    const THETA: f32 = 0.5;
+    const SOFTENING: f32 = 0.1;
+
+    // 固定种子的 1000 个天体, 分布在边长 100 的立方体中
+    fn random_bodies() -> Vec<(Vec3, f32)> {
+        let mut rng = ChaCha8Rng::seed_from_u64(1000);
+        (0..1000)
+            .map(|_| {
+                let position = Vec3::new(
+                    rng.random_range(-50.0..50.0),
+                    rng.random_range(-50.0..50.0),
+                    rng.random_range(-50.0..50.0),
+                );
+                (position, rng.random_range(1.0..10.0))
+            })
+            .collect()
+    }
+
+    fn accelerations(kind: SolverKind, bodies: &[(Vec3, f32)]) -> Vec<Vec3> {
+        let solver = ForceSolver {
+            kind,
+            theta: THETA,
+            softening: SOFTENING,
+        };
+        let (positions, masses): (Vec<Vec3>, Vec<f32>) = bodies.iter().copied().unzip();
+        solver.accelerations(&positions, &masses)
+    }
+
+    #[test]
+    fn barnes_hut_error_is_bounded() {
+        let bodies = random_bodies();
+        let exact = accelerations(SolverKind::Exact, &bodies);
+        let octree = Octree::build(&bodies);
+        let max_error = bodies
+            .iter()
+            .zip(&exact)
+            .map(|((position, _), exact)| {
+                let approx = octree.acceleration(*position, THETA, SOFTENING);
+                approx.distance(*exact) / exact.length()
+            })
+            .fold(0.0, f32::max);
+        assert!(max_error < 0.05, "max relative error {max_error}");
+    }
+
+    // θ = 0 时每个节点都会展开到叶子, 与精确解只差累加顺序
+    #[test]
+    fn zero_theta_matches_exact() {
+        let bodies = random_bodies();
+        let exact = accelerations(SolverKind::Exact, &bodies);
+        let octree = Octree::build(&bodies);
+        for ((position, _), exact) in bodies.iter().zip(&exact) {
+            let approx = octree.acceleration(*position, 0.0, SOFTENING);
+            assert!(approx.distance(*exact) <= 1e-4 * exact.length());
+        }
+    }
+
+    // θ 很大时几乎所有节点都看作质点, 但包含自身的节点仍要展开
+    // 只有内力, 总的力应该接近 0
+    // 两个质量不同的天体时, 轻的那个会把包含自身的根节点看作质点, 最容易暴露自身引力
+    #[test]
+    fn large_theta_has_no_self_force() {
+        let pair = vec![(Vec3::ZERO, 1.0), (Vec3::ONE, 3.0)];
+        for bodies in [pair, random_bodies()] {
+            let octree = Octree::build(&bodies);
+            let (total, scale) =
+                bodies
+                    .iter()
+                    .fold((Vec3::ZERO, 0.0), |(total, scale), (position, mass)| {
+                        let force = octree.acceleration(*position, 1.5, SOFTENING) * *mass;
+                        (total + force, scale + force.length())
+                    });
+            assert!(
+                total.length() < 1e-2 * scale,
+                "total force {total}, sum of magnitudes {scale}"
+            );
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! 轨道预测
+//! 复制当前所有天体得到一个"幽灵"模拟, 用相同的 solver、积分器和子步设置向前推进, 得到选中天体未来的路径
+//! 幽灵模拟不处理碰撞合并, 发生合并后天体集合改变, 预测会重新计算
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use super::{Bodies, ForceSolver, Integrator, LastPos, Mass, Substepping, Velocity};
+
+// 脱离 ECS 的模拟, 与 NBodyPlugin 的 Forces + Integrate 完全相同
+#[derive(Debug, Clone)]
+pub struct GhostSimulation {
+    pub bodies: Bodies,
+    pub integrator: Integrator,
+    pub solver: ForceSolver,
+    pub substepping: Substepping,
+}
+
+impl GhostSimulation {
+    pub fn step(&mut self, dt: f32) {
+        let accelerations = self
+            .solver
+            .accelerations(&self.bodies.positions, &self.bodies.masses);
+        self.integrator.advance(
+            &mut self.bodies,
+            &accelerations,
+            dt,
+            &self.solver,
+            &self.substepping,
+        );
+    }
+
+    // 向前推进 steps 步, 返回第 index 个天体每一步之后的位置
+    pub fn predict(&mut self, index: usize, dt: f32, steps: usize) -> Vec<Vec3> {
+        (0..steps)
+            .map(|_| {
+                self.step(dt);
+                self.bodies.positions[index]
+            })
+            .collect()
+    }
+}
+
+// 插入这个资源后 NBodyPlugin 开始预测 target 的轨道
+#[derive(Resource, Debug, Clone)]
+pub struct OrbitPrediction {
+    pub target: Option<Entity>,
+    // 预测的时长 (秒)
+    pub horizon: f32,
+    path: Vec<Vec3>,
+    // 预测之后真实模拟已经走过的步数
+    consumed: usize,
+    stale: bool,
+}
+
+impl OrbitPrediction {
+    pub fn new(horizon: f32) -> Self {
+        Self {
+            target: None,
+            horizon,
+            path: Vec::new(),
+            consumed: 0,
+            stale: true,
+        }
+    }
+
+    pub fn select(&mut self, target: Option<Entity>) {
+        if self.target != target {
+            self.target = target;
+            self.stale = true;
+        }
+    }
+
+    // 下一个固定步开始的预测位置, 第 i 个是 i + 1 步之后的位置
+    pub fn path(&self) -> &[Vec3] {
+        self.path.get(self.consumed..).unwrap_or_default()
+    }
+
+    // 下次 FixedUpdate 时重新预测
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+}
+
+// 幽灵模拟需要的全部设置
+#[derive(SystemParam)]
+pub(crate) struct SimulationSettings<'w> {
+    integrator: Res<'w, Integrator>,
+    solver: Res<'w, ForceSolver>,
+    substepping: Res<'w, Substepping>,
+}
+
+impl SimulationSettings<'_> {
+    fn is_changed(&self) -> bool {
+        self.integrator.is_changed() || self.solver.is_changed() || self.substepping.is_changed()
+    }
+
+    fn ghost(&self, bodies: Bodies) -> GhostSimulation {
+        GhostSimulation {
+            bodies,
+            integrator: *self.integrator,
+            solver: self.solver.clone(),
+            substepping: self.substepping.clone(),
+        }
+    }
+}
+
+// 天体集合、模拟设置改变或预测用完时重新计算, 否则只前进一步
+pub(crate) fn update_prediction(
+    time: Res<Time>,
+    settings: SimulationSettings,
+    mut prediction: ResMut<OrbitPrediction>,
+    added: Query<(), Added<Mass>>,
+    mut removed: RemovedComponents<Mass>,
+    query: Query<(Entity, &Mass, &Transform, &LastPos, &Velocity)>,
+) {
+    let dt = time.delta_secs();
+    let bodies_changed = !added.is_empty() || removed.read().count() > 0;
+    if !prediction.stale
+        && !bodies_changed
+        && !settings.is_changed()
+        && prediction.consumed < prediction.path.len()
+    {
+        prediction.consumed += 1;
+        return;
+    }
+
+    prediction.stale = false;
+    prediction.consumed = 0;
+    prediction.path.clear();
+    let Some(target) = prediction.target else {
+        return;
+    };
+    if dt <= 0.0 {
+        prediction.stale = true;
+        return;
+    }
+
+    let mut bodies = Bodies::default();
+    let mut index = None;
+    for (entity, mass, transform, last_pos, velocity) in &query {
+        if entity == target {
+            index = Some(bodies.masses.len());
+        }
+        bodies.masses.push(mass.0);
+        bodies.positions.push(transform.translation);
+        bodies.last_positions.push(last_pos.0);
+        bodies.velocities.push(velocity.0);
+    }
+    // 选中的天体已经被合并
+    let Some(index) = index else {
+        prediction.target = None;
+        return;
+    };
+
+    let mut ghost = settings.ghost(bodies);
+    let steps = (prediction.horizon / dt).ceil() as usize;
+    prediction.path = ghost.predict(index, dt, steps);
+}
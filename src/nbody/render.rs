@@ -0,0 +1,147 @@
+//! n-body 渲染, 给物理天体加上网格、材质、光源, 并创建相机
+//! 只在需要画面时添加, 无头运行时不需要
+//! 存在 TrajectoryRecorder 时用 Gizmos 画出渐隐的轨迹, 存在 OrbitPrediction 时画出预测路径
+
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::camera_controller::{CameraController, CameraTarget};
+
+use super::{BodyColor, Mass, OrbitPrediction, Star, TrajectoryRecorder};
+
+pub struct NBodyRenderPlugin;
+
+impl Plugin for NBodyRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // 黑色背景
+            .insert_resource(ClearColor(Color::BLACK))
+            .add_systems(Startup, spawn_camera)
+            .add_systems(
+                Update,
+                (
+                    attach_body_meshes,
+                    draw_trails.run_if(resource_exists::<TrajectoryRecorder>),
+                    draw_prediction.run_if(resource_exists::<OrbitPrediction>),
+                ),
+            );
+    }
+}
+
+// 需要 CameraControllerPlugin 才能控制相机
+fn spawn_camera(mut commands: Commands) {
+    // 创建相机
+    // 相机位置 Transform::from_xyz(0.0, 10.5, -30.0)
+    // 相机朝向 .look_at(Vec3::ZERO, Vec3::Y) 看向ZERO 点位, 摄像头顶部朝向Y轴
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(0.0, 10.5, -30.0).looking_at(Vec3::ZERO, Vec3::Y),
+        CameraController::looking_from(Vec3::ZERO, Vec3::new(0.0, 10.5, -30.0)),
+    ));
+}
+
+// 新出现的天体加上网格和材质, 恒星额外加上光源, 所有天体都可以被相机跟随
+fn attach_body_meshes(
+    mut commands: Commands,
+    bodies: Query<(Entity, &BodyColor, &Transform, Has<Star>), Added<Mass>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    // 所有天体共用的球体网格 (普通天体, 恒星)
+    mut spheres: Local<Option<(Handle<Mesh>, Handle<Mesh>)>>,
+) {
+    if bodies.is_empty() {
+        return;
+    }
+    // Sphere::new(1.0) 创建半径为1的球体参数
+    // .mesh() 生成 MeshBuilder (网格构建器)
+    // .ico(3) 用 ico 球算法细分3次
+    // ico (0 20面 1 80面 2 320面 3 1280面 4 5120面)
+    // 加入到资源中,用于后续渲染
+    let (body_mesh, star_mesh) = spheres.get_or_insert_with(|| {
+        (
+            meshes.add(Sphere::new(1.0).mesh().ico(3).unwrap()),
+            meshes.add(Sphere::new(1.0).mesh().ico(5).unwrap()),
+        )
+    });
+
+    for (entity, color, transform, is_star) in &bodies {
+        if is_star {
+            commands
+                .entity(entity)
+                .insert((
+                    CameraTarget,
+                    Mesh3d(star_mesh.clone()),
+                    MeshMaterial3d(materials.add(StandardMaterial {
+                        base_color: color.0,
+                        emissive: LinearRgba::from(color.0) * 2.,
+                        ..default()
+                    })),
+                ))
+                // 添加光源
+                .with_child(PointLight {
+                    color: Color::WHITE,
+                    range: 100.0,
+                    radius: transform.scale.x,
+                    ..default()
+                });
+        } else {
+            commands.entity(entity).insert((
+                CameraTarget,
+                Mesh3d(body_mesh.clone()),
+                MeshMaterial3d(materials.add(color.0)),
+            ));
+        }
+    }
+}
+
+// 越旧的点越透明, 已经被合并删除的天体没有颜色, 画成灰色直到移出缓冲区
+fn draw_trails(mut gizmos: Gizmos, recorder: Res<TrajectoryRecorder>, colors: Query<&BodyColor>) {
+    let frames = recorder.frames().len();
+    let mut trails: HashMap<Entity, Vec<(Vec3, f32)>> = HashMap::new();
+    for (i, frame) in recorder.frames().enumerate() {
+        let alpha = (i + 1) as f32 / frames as f32;
+        for sample in &frame.samples {
+            trails
+                .entry(sample.entity)
+                .or_default()
+                .push((sample.position, alpha));
+        }
+    }
+
+    for (entity, trail) in trails {
+        let color = colors
+            .get(entity)
+            .map_or(Color::srgb(0.5, 0.5, 0.5), |color| color.0);
+        gizmos.linestrip_gradient(
+            trail
+                .into_iter()
+                .map(|(position, alpha)| (position, color.with_alpha(alpha * 0.8))),
+        );
+    }
+}
+
+// 选中的天体外画一个圈, 预测路径越远越暗
+fn draw_prediction(
+    mut gizmos: Gizmos,
+    prediction: Res<OrbitPrediction>,
+    bodies: Query<&Transform, With<Mass>>,
+) {
+    let Some(transform) = prediction.target.and_then(|target| bodies.get(target).ok()) else {
+        return;
+    };
+    gizmos.sphere(
+        Isometry3d::from_translation(transform.translation),
+        transform.scale.x * 1.5,
+        Color::WHITE,
+    );
+
+    let path = prediction.path();
+    gizmos.linestrip_gradient(
+        std::iter::once(transform.translation)
+            .chain(path.iter().copied())
+            .enumerate()
+            .map(|(i, position)| {
+                let alpha = 1.0 - i as f32 / (path.len() + 1) as f32;
+                (position, Color::srgba(0.4, 1.0, 0.4, alpha))
+            }),
+    );
+}
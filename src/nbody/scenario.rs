@@ -0,0 +1,504 @@
+//! 场景 (初始条件)
+//! 每个天体的质量、位置、速度、半径、颜色, 可以保存为 CSV 或 RON 文件, 也可以从内置预设生成
+
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use bevy::{color::palettes::css::ORANGE_RED, math::FloatPow, prelude::*};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{BodyBundle, BodyColor, GRAVITY_CONSTANT, LastPos, Mass, NUM_BODIES, Star, Velocity};
+
+const CSV_HEADER: &str = "mass,x,y,z,vx,vy,vz,radius,r,g,b,star";
+
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    #[error("failed to access scenario `{path}`: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid RON scenario: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("failed to write RON scenario: {0}")]
+    RonWrite(#[from] ron::Error),
+    #[error("invalid CSV scenario at line {line}: {message}")]
+    Csv { line: usize, message: String },
+    #[error("unknown scenario format `{0}`, expected .csv or .ron")]
+    UnknownFormat(PathBuf),
+}
+
+// 文件格式, 由扩展名决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScenarioFormat {
+    Csv,
+    Ron,
+}
+
+impl ScenarioFormat {
+    pub fn from_path(path: &Path) -> Result<Self, ScenarioError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Ok(ScenarioFormat::Csv),
+            Some("ron") => Ok(ScenarioFormat::Ron),
+            _ => Err(ScenarioError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+}
+
+// 一个天体的初始状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BodySpec {
+    pub mass: f32,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub radius: f32,
+    // sRGB
+    pub color: [f32; 3],
+    #[serde(default)]
+    pub star: bool,
+}
+
+// NBodyPlugin 默认使用随机球体, 在添加插件前插入资源即可替换
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    pub bodies: Vec<BodySpec>,
+}
+
+impl FromIterator<BodySpec> for Scenario {
+    fn from_iter<I: IntoIterator<Item = BodySpec>>(iter: I) -> Self {
+        Scenario {
+            bodies: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let format = ScenarioFormat::from_path(path)?;
+        let text = std::fs::read_to_string(path).map_err(|source| ScenarioError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        match format {
+            ScenarioFormat::Csv => Self::from_csv(&text),
+            ScenarioFormat::Ron => Self::from_ron(&text),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ScenarioError> {
+        let path = path.as_ref();
+        let text = match ScenarioFormat::from_path(path)? {
+            ScenarioFormat::Csv => self.to_csv(),
+            ScenarioFormat::Ron => self.to_ron()?,
+        };
+        let io_error = |source| ScenarioError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        std::fs::write(path, text).map_err(io_error)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ScenarioError> {
+        Ok(ron::from_str(text)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String, ScenarioError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    // 第一行是表头, 空行和 # 开头的行被忽略
+    pub fn from_csv(text: &str) -> Result<Self, ScenarioError> {
+        let mut bodies = Vec::new();
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, header)) if header.replace(' ', "") == CSV_HEADER => {}
+            Some((line, _)) => {
+                return Err(ScenarioError::Csv {
+                    line,
+                    message: format!("expected header `{CSV_HEADER}`"),
+                });
+            }
+            None => return Ok(Scenario { bodies }),
+        }
+
+        for (line, row) in lines {
+            let csv_error = |message: String| ScenarioError::Csv { line, message };
+            let fields: Vec<&str> = row.split(',').map(str::trim).collect();
+            if fields.len() != 12 {
+                return Err(csv_error(format!(
+                    "expected 12 fields, found {}",
+                    fields.len()
+                )));
+            }
+            let mut numbers = [0.0; 11];
+            for (number, field) in numbers.iter_mut().zip(&fields) {
+                *number = field
+                    .parse()
+                    .map_err(|_| csv_error(format!("`{field}` is not a number")))?;
+            }
+            let star = fields[11]
+                .parse()
+                .map_err(|_| csv_error(format!("`{}` is not true or false", fields[11])))?;
+            let [mass, x, y, z, vx, vy, vz, radius, r, g, b] = numbers;
+            bodies.push(BodySpec {
+                mass,
+                position: Vec3::new(x, y, z),
+                velocity: Vec3::new(vx, vy, vz),
+                radius,
+                color: [r, g, b],
+                star,
+            });
+        }
+        Ok(Scenario { bodies })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{CSV_HEADER}\n");
+        for body in &self.bodies {
+            let [r, g, b] = body.color;
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{},{},{}",
+                body.mass,
+                body.position.x,
+                body.position.y,
+                body.position.z,
+                body.velocity.x,
+                body.velocity.y,
+                body.velocity.z,
+                body.radius,
+                r,
+                g,
+                b,
+                body.star,
+            );
+        }
+        csv
+    }
+
+    // 位置 Verlet 需要上一步的位置, 由速度和时间步长反推
+    pub fn spawn(&self, commands: &mut Commands, timestep: f32) {
+        for body in &self.bodies {
+            let mut entity = commands.spawn((
+                BodyBundle {
+                    mass: Mass(body.mass),
+                    last_pos: LastPos(body.position - body.velocity * timestep),
+                    velocity: Velocity(body.velocity),
+                    ..default()
+                },
+                Transform {
+                    translation: body.position,
+                    scale: Vec3::splat(body.radius),
+                    ..default()
+                },
+                BodyColor(Color::srgb_from_array(body.color)),
+            ));
+            if body.star {
+                entity.insert(Star);
+            }
+        }
+    }
+
+    // 减去质心速度, 让整个系统不漂移
+    fn remove_net_momentum(&mut self) {
+        let mass: f32 = self.bodies.iter().map(|body| body.mass).sum();
+        let momentum: Vec3 = self
+            .bodies
+            .iter()
+            .map(|body| body.velocity * body.mass)
+            .sum();
+        for body in &mut self.bodies {
+            body.velocity -= momentum / mass;
+        }
+    }
+}
+
+// 内置预设
+// 引力大小为 G·m1·m2/r, 圆轨道速度 v = √(G·M) 与半径无关
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    // 原来的随机球体加中心恒星
+    RandomSphere,
+    // 两颗等质量恒星互相绕转
+    BinaryStar,
+    SunEarthMoon,
+    // 中心恒星加绕 Y 轴旋转的圆盘
+    DiskGalaxy,
+    // 没有恒星的球状星团
+    PlummerSphere,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::RandomSphere,
+        Preset::BinaryStar,
+        Preset::SunEarthMoon,
+        Preset::DiskGalaxy,
+        Preset::PlummerSphere,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::RandomSphere => "random_sphere",
+            Preset::BinaryStar => "binary_star",
+            Preset::SunEarthMoon => "sun_earth_moon",
+            Preset::DiskGalaxy => "disk_galaxy",
+            Preset::PlummerSphere => "plummer_sphere",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Preset::ALL.into_iter().find(|preset| preset.name() == name)
+    }
+
+    pub fn scenario(self) -> Scenario {
+        let mut rng = ChaCha8Rng::seed_from_u64(19878367467713);
+        match self {
+            Preset::RandomSphere => random_sphere(&mut rng),
+            Preset::BinaryStar => binary_star(&mut rng),
+            Preset::SunEarthMoon => sun_earth_moon(),
+            Preset::DiskGalaxy => disk_galaxy(&mut rng),
+            Preset::PlummerSphere => plummer_sphere(&mut rng),
+        }
+    }
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Preset::RandomSphere.scenario()
+    }
+}
+
+fn star(mass: f32, position: Vec3, velocity: Vec3) -> BodySpec {
+    BodySpec {
+        mass,
+        position,
+        velocity,
+        radius: 1.1,
+        color: ORANGE_RED.to_f32_array_no_alpha(),
+        star: true,
+    }
+}
+
+// 随机半径, 体积 ∝ 半径³
+fn random_body(rng: &mut ChaCha8Rng, position: Vec3, velocity: Vec3) -> BodySpec {
+    let radius: f32 = rng.random_range(0.1..0.25);
+    BodySpec {
+        mass: FloatPow::cubed(radius) * 10.,
+        position,
+        velocity,
+        radius,
+        color: random_color(rng),
+        star: false,
+    }
+}
+
+fn random_color(rng: &mut ChaCha8Rng) -> [f32; 3] {
+    // 随机颜色范围
+    let color_range = 0.5..1.0;
+    [
+        rng.random_range(color_range.clone()),
+        rng.random_range(color_range.clone()),
+        rng.random_range(color_range.clone()),
+    ]
+}
+
+fn random_direction(rng: &mut ChaCha8Rng) -> Vec3 {
+    Vec3::new(
+        rng.random_range(-1.0..1.0),
+        rng.random_range(-1.0..1.0),
+        rng.random_range(-1.0..1.0),
+    )
+    .normalize()
+}
+
+// 随机数的顺序和原来的 generate_bodies 一致, 生成的天体完全相同
+fn random_sphere(rng: &mut ChaCha8Rng) -> Scenario {
+    let vel_range = -0.5..0.5;
+
+    let mut scenario: Scenario = (0..NUM_BODIES)
+        .map(|_| {
+            // 随机半径 在浮点数中 0.1..0.7 表示区间,不能直接转化为数组
+            let radius: f32 = rng.random_range(0.1..0.7);
+            let position = random_direction(rng) * ops::cbrt(rng.random_range(0.2f32..1.0)) * 15.0;
+            let color = random_color(rng);
+            let velocity = Vec3::new(
+                rng.random_range(vel_range.clone()),
+                rng.random_range(vel_range.clone()),
+                rng.random_range(vel_range.clone()),
+            );
+            BodySpec {
+                mass: FloatPow::cubed(radius) * 10.,
+                position,
+                velocity,
+                radius,
+                color,
+                star: false,
+            }
+        })
+        .collect();
+
+    scenario.bodies.push(star(500.0, Vec3::ZERO, Vec3::ZERO));
+    scenario
+}
+
+// 两颗恒星绕质心转动, 外围一圈小天体绕整个双星系统
+fn binary_star(rng: &mut ChaCha8Rng) -> Scenario {
+    let mass = 250.0;
+    let separation = 6.0;
+    // 对方的引力 G·m/d 提供向心加速度 v²/(d/2)
+    let speed = ops::sqrt(GRAVITY_CONSTANT * mass / 2.0);
+    let mut scenario = Scenario {
+        bodies: vec![
+            star(mass, Vec3::X * separation / 2.0, Vec3::NEG_Z * speed),
+            star(mass, Vec3::NEG_X * separation / 2.0, Vec3::Z * speed),
+        ],
+    };
+
+    // 远处把双星当作一个质点
+    let orbit_speed = ops::sqrt(GRAVITY_CONSTANT * mass * 2.0);
+    for i in 0..NUM_BODIES / 2 {
+        let angle = i as f32 / (NUM_BODIES / 2) as f32 * std::f32::consts::TAU;
+        let radius = rng.random_range(14.0..18.0);
+        let (sin, cos) = ops::sin_cos(angle);
+        let position = Vec3::new(cos, 0.0, sin) * radius;
+        let velocity = Vec3::new(-sin, 0.0, cos) * orbit_speed;
+        scenario.bodies.push(random_body(rng, position, velocity));
+    }
+    scenario.remove_net_momentum();
+    scenario
+}
+
+fn sun_earth_moon() -> Scenario {
+    let sun = 500.0;
+    let earth = 80.0;
+    let moon = 0.05;
+    let earth_orbit = 12.0;
+    // 月球离地球足够近, 地球的引力远大于太阳的潮汐力
+    let moon_orbit = 0.6;
+    // 相对速度由两者的总质量决定
+    let earth_velocity = Vec3::Z * ops::sqrt(GRAVITY_CONSTANT * (sun + earth + moon));
+    let moon_velocity = earth_velocity + Vec3::Z * ops::sqrt(GRAVITY_CONSTANT * (earth + moon));
+
+    let mut scenario = Scenario {
+        bodies: vec![
+            star(sun, Vec3::ZERO, Vec3::ZERO),
+            BodySpec {
+                mass: earth,
+                position: Vec3::X * earth_orbit,
+                velocity: earth_velocity,
+                radius: 0.25,
+                color: [0.2, 0.4, 1.0],
+                star: false,
+            },
+            BodySpec {
+                mass: moon,
+                position: Vec3::X * (earth_orbit + moon_orbit),
+                velocity: moon_velocity,
+                radius: 0.1,
+                color: [0.8, 0.8, 0.8],
+                star: false,
+            },
+        ],
+    };
+    scenario.remove_net_momentum();
+    scenario
+}
+
+// 圆盘在 XZ 平面, 每个天体的圆轨道速度由内侧的总质量决定
+fn disk_galaxy(rng: &mut ChaCha8Rng) -> Scenario {
+    let star_mass = 500.0;
+    let mut bodies: Vec<BodySpec> = (0..NUM_BODIES)
+        .map(|_| {
+            let radius = rng.random_range(3.0..15.0);
+            let angle = rng.random_range(0.0..std::f32::consts::TAU);
+            let (sin, cos) = ops::sin_cos(angle);
+            let position = Vec3::new(cos * radius, rng.random_range(-0.3..0.3), sin * radius);
+            random_body(rng, position, Vec3::ZERO)
+        })
+        .collect();
+
+    bodies.sort_by(|a, b| a.position.length().total_cmp(&b.position.length()));
+    let mut enclosed = star_mass;
+    for body in &mut bodies {
+        let tangent = Vec3::Y.cross(body.position).normalize();
+        body.velocity = tangent * ops::sqrt(GRAVITY_CONSTANT * enclosed);
+        enclosed += body.mass;
+    }
+
+    bodies.push(star(star_mass, Vec3::ZERO, Vec3::ZERO));
+    let mut scenario = Scenario { bodies };
+    scenario.remove_net_momentum();
+    scenario
+}
+
+// Plummer 模型 (Aarseth 1974 的抽样方法)
+// 抽样公式针对 1/r² 引力, 这里的引力是 1/r, 所以最后按维里定理 2K = G·Σm_i·m_j 缩放速度
+fn plummer_sphere(rng: &mut ChaCha8Rng) -> Scenario {
+    let scale = 5.0;
+    let mass = 1.0;
+    let radius = 0.2;
+
+    let mut scenario: Scenario = (0..NUM_BODIES)
+        .map(|_| {
+            // 截断在 10 倍尺度内
+            let r = loop {
+                let x: f32 = rng.random_range(0.0..1.0);
+                let r = 1.0 / ops::sqrt(ops::powf(x, -2.0 / 3.0) - 1.0);
+                if r.is_finite() && r < 10.0 {
+                    break r;
+                }
+            };
+            // 拒绝抽样 g(q) = q²(1 - q²)^3.5
+            let q = loop {
+                let q: f32 = rng.random_range(0.0..1.0);
+                let g: f32 = rng.random_range(0.0..0.1);
+                if g < q * q * ops::powf(1.0 - q * q, 3.5) {
+                    break q;
+                }
+            };
+            let speed = q * std::f32::consts::SQRT_2 * ops::powf(1.0 + r * r, -0.25);
+            BodySpec {
+                mass,
+                position: random_direction(rng) * r * scale,
+                velocity: random_direction(rng) * speed,
+                radius,
+                color: random_color(rng),
+                star: false,
+            }
+        })
+        .collect();
+    scenario.remove_net_momentum();
+
+    let kinetic: f32 = scenario
+        .bodies
+        .iter()
+        .map(|body| 0.5 * body.mass * body.velocity.length_squared())
+        .sum();
+    let total_mass = mass * NUM_BODIES as f32;
+    // Σ_{i<j} m_i·m_j, 所有质量相同
+    let virial =
+        GRAVITY_CONSTANT * (total_mass * total_mass - NUM_BODIES as f32 * mass * mass) / 2.0;
+    let factor = ops::sqrt(virial / 2.0 / kinetic);
+    for body in &mut scenario.bodies {
+        body.velocity *= factor;
+    }
+    scenario
+}
@@ -0,0 +1,301 @@
+//! 引力求解
+//! 精确解使用 iter_combinations 两两计算, 近似解使用 Barnes–Hut 八叉树
+//! 并行精确解用 par_iter 逐个天体按固定顺序累加, 结果与线程数无关
+
+use bevy::{
+    prelude::*,
+    tasks::{ComputeTaskPool, ParallelSlice, TaskPool},
+};
+
+use super::{Acceleration, GRAVITY_CONSTANT, Mass, octree::Octree};
+
+// 引力求解方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolverKind {
+    // iter_combinations 两两计算 O(n²)
+    Exact,
+    // par_iter 逐个天体计算 O(n²), 每对天体算两次, 但可以并行
+    Parallel,
+    // 八叉树近似 O(n log n)
+    BarnesHut,
+}
+
+impl SolverKind {
+    pub fn next(self) -> Self {
+        match self {
+            SolverKind::Exact => SolverKind::Parallel,
+            SolverKind::Parallel => SolverKind::BarnesHut,
+            SolverKind::BarnesHut => SolverKind::Exact,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct ForceSolver {
+    pub kind: SolverKind,
+    // 张角 θ: 节点边长 / 距离 < θ 时把整个节点当作一个质点, 越小越精确, 0 等于精确解
+    pub theta: f32,
+    // Plummer 软化长度 ε, 距离小于 ε 时引力不再发散, 0 为不软化
+    pub softening: f32,
+}
+
+impl Default for ForceSolver {
+    fn default() -> Self {
+        Self {
+            kind: SolverKind::Exact,
+            theta: 0.5,
+            softening: 0.0,
+        }
+    }
+}
+
+impl ForceSolver {
+    // θ 超过 1 时误差已经很大, 调整 θ 时以此为上限
+    pub const MAX_THETA: f32 = 1.0;
+
+    // 不经过 ECS 直接计算一组位置的加速度, 积分器的中间阶段使用
+    pub fn accelerations(&self, positions: &[Vec3], masses: &[f32]) -> Vec<Vec3> {
+        match self.kind {
+            SolverKind::Exact => {
+                let mut accelerations = vec![Vec3::ZERO; positions.len()];
+                for i in 0..positions.len() {
+                    for j in i + 1..positions.len() {
+                        let force_unit_mass =
+                            force_unit_mass(positions[j] - positions[i], self.softening);
+                        accelerations[i] += force_unit_mass * masses[j];
+                        accelerations[j] -= force_unit_mass * masses[i];
+                    }
+                }
+                accelerations
+            }
+            SolverKind::Parallel => self.parallel_accelerations(
+                ComputeTaskPool::get_or_init(TaskPool::default),
+                positions,
+                masses,
+            ),
+            SolverKind::BarnesHut => {
+                let bodies: Vec<(Vec3, f32)> = positions
+                    .iter()
+                    .copied()
+                    .zip(masses.iter().copied())
+                    .collect();
+                let octree = Octree::build(&bodies);
+                positions
+                    .iter()
+                    .map(|position| octree.acceleration(*position, self.theta, self.softening))
+                    .collect()
+            }
+        }
+    }
+
+    // 在指定的线程池中逐个天体计算, 分块大小只影响任务划分, 不影响每个天体的累加顺序
+    pub fn parallel_accelerations(
+        &self,
+        pool: &TaskPool,
+        positions: &[Vec3],
+        masses: &[f32],
+    ) -> Vec<Vec3> {
+        let bodies: Vec<(Vec3, f32)> = positions
+            .iter()
+            .copied()
+            .zip(masses.iter().copied())
+            .collect();
+        positions
+            .par_chunk_map(pool, PARALLEL_CHUNK_SIZE, |chunk_index, chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .map(|(i, position)| {
+                        let index = chunk_index * PARALLEL_CHUNK_SIZE + i;
+                        sum_accelerations(
+                            *position,
+                            self.softening,
+                            bodies
+                                .iter()
+                                .enumerate()
+                                .filter(|(j, _)| *j != index)
+                                .map(|(_, body)| *body),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+pub(crate) fn interact_bodies(
+    solver: Res<ForceSolver>,
+    mut query: Query<(&Mass, &Transform, &mut Acceleration)>,
+) {
+    let mut iter = query.iter_combinations_mut();
+    // 两两组合
+    while let Some(
+        [
+            (Mass(m1), transform1, mut acc1),
+            (Mass(m2), transform2, mut acc2),
+        ],
+    ) = iter.fetch_next()
+    {
+        let force_unit_mass = force_unit_mass(
+            transform2.translation - transform1.translation,
+            solver.softening,
+        );
+        acc1.0 += force_unit_mass * *m2;
+        acc2.0 -= force_unit_mass * *m1;
+    }
+}
+
+// 每个天体的加速度只由一个线程计算, 按其他天体的顺序依次累加
+// 浮点加法不满足结合律, 固定顺序才能保证结果与线程数无关
+pub(crate) fn parallel_interact_bodies(
+    solver: Res<ForceSolver>,
+    mut query: Query<(Entity, &Mass, &Transform, &mut Acceleration)>,
+) {
+    let bodies: Vec<(Entity, Vec3, f32)> = query
+        .iter()
+        .map(|(entity, Mass(mass), transform, _)| (entity, transform.translation, *mass))
+        .collect();
+    query
+        .par_iter_mut()
+        .for_each(|(entity, _, transform, mut acceleration)| {
+            acceleration.0 += sum_accelerations(
+                transform.translation,
+                solver.softening,
+                bodies
+                    .iter()
+                    .filter(|(other, ..)| *other != entity)
+                    .map(|(_, position, mass)| (*position, *mass)),
+            );
+        });
+}
+
+const PARALLEL_CHUNK_SIZE: usize = 32;
+
+// position 处受到 others (位置, 质量) 的加速度之和
+fn sum_accelerations(
+    position: Vec3,
+    softening: f32,
+    others: impl Iterator<Item = (Vec3, f32)>,
+) -> Vec3 {
+    others.fold(Vec3::ZERO, |acceleration, (other, mass)| {
+        acceleration + force_unit_mass(other - position, softening) * mass
+    })
+}
+
+// 单位质量受到的引力, 精确解和八叉树共用
+// Plummer 软化: 距离平方加上 ε², 两个天体重合时引力为 0
+pub fn force_unit_mass(delta: Vec3, softening: f32) -> Vec3 {
+    let distance_sq: f32 = delta.length_squared() + softening * softening;
+    let f = GRAVITY_CONSTANT / distance_sq;
+    delta * f
+}
+
+pub fn solver_is(kind: SolverKind) -> impl Fn(Res<ForceSolver>) -> bool {
+    move |solver: Res<ForceSolver>| solver.kind == kind
+}
+
+// 每个 FixedUpdate 重新建树, 再逐个天体遍历树求加速度
+pub(crate) fn barnes_hut_bodies(
+    solver: Res<ForceSolver>,
+    mut query: Query<(&Mass, &Transform, &mut Acceleration)>,
+) {
+    let bodies: Vec<(Vec3, f32)> = query
+        .iter()
+        .map(|(Mass(mass), transform, _)| (transform.translation, *mass))
+        .collect();
+    let octree = Octree::build(&bodies);
+    for (_, transform, mut acceleration) in &mut query {
+        acceleration.0 +=
+            octree.acceleration(transform.translation, solver.theta, solver.softening);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, tasks::TaskPoolBuilder};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    // 天体数不是分块大小的整数倍, 最后一块不满
+    fn random_bodies() -> (Vec<Vec3>, Vec<f32>) {
+        let mut rng = ChaCha8Rng::seed_from_u64(37);
+        (0..PARALLEL_CHUNK_SIZE * 10 + 7)
+            .map(|_| {
+                let position = Vec3::new(
+                    rng.random_range(-20.0..20.0),
+                    rng.random_range(-20.0..20.0),
+                    rng.random_range(-20.0..20.0),
+                );
+                (position, rng.random_range(0.1..5.0))
+            })
+            .unzip()
+    }
+
+    fn solver(kind: SolverKind) -> ForceSolver {
+        ForceSolver {
+            kind,
+            softening: 0.05,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn parallel_is_independent_of_thread_count() {
+        let (positions, masses) = random_bodies();
+        let parallel = solver(SolverKind::Parallel);
+        let single = TaskPoolBuilder::new().num_threads(1).build();
+        let expected = parallel.parallel_accelerations(&single, &positions, &masses);
+        for threads in [2, 3, 8] {
+            let pool = TaskPoolBuilder::new().num_threads(threads).build();
+            let accelerations = parallel.parallel_accelerations(&pool, &positions, &masses);
+            // 逐位相同, 不是近似相等
+            let same_bits = accelerations
+                .iter()
+                .zip(&expected)
+                .all(|(a, b)| a.to_array().map(f32::to_bits) == b.to_array().map(f32::to_bits));
+            assert!(same_bits, "{threads} threads differ from 1 thread");
+        }
+    }
+
+    // 与精确解只差累加顺序
+    #[test]
+    fn parallel_matches_exact() {
+        let (positions, masses) = random_bodies();
+        let exact = solver(SolverKind::Exact).accelerations(&positions, &masses);
+        let parallel = solver(SolverKind::Parallel).accelerations(&positions, &masses);
+        for (parallel, exact) in parallel.iter().zip(&exact) {
+            assert!(parallel.distance(*exact) <= 1e-4 * exact.length());
+        }
+    }
+
+    // ECS 中的 parallel_interact_bodies 与 ForceSolver 使用同样的累加顺序
+    #[test]
+    fn parallel_system_matches_solver() {
+        let (positions, masses) = random_bodies();
+        let mut world = World::new();
+        world.insert_resource(solver(SolverKind::Parallel));
+        let entities: Vec<Entity> = positions
+            .iter()
+            .zip(&masses)
+            .map(|(position, mass)| {
+                world
+                    .spawn((
+                        Mass(*mass),
+                        Transform::from_translation(*position),
+                        Acceleration::default(),
+                    ))
+                    .id()
+            })
+            .collect();
+        world.run_system_once(parallel_interact_bodies).unwrap();
+
+        let expected = solver(SolverKind::Parallel).accelerations(&positions, &masses);
+        for (entity, expected) in entities.iter().zip(&expected) {
+            assert_eq!(world.get::<Acceleration>(*entity).unwrap().0, *expected);
+        }
+    }
+}
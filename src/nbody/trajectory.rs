@@ -0,0 +1,185 @@
+//! 轨迹记录
+//! 每隔 stride 个固定步记录一次所有天体的位置和速度, 存在环形缓冲区中
+//! 可以导出为 CSV 或二进制文件离线绘图, 渲染插件会把它画成渐隐的轨迹
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use thiserror::Error;
+
+use super::Velocity;
+
+// 二进制格式 (小端):
+// 文件头 b"NBTR", 版本 u32, 帧数 u32
+// 每帧 step u64, time f32, 天体数 u32, 然后每个天体 entity u64, 位置 3×f32, 速度 3×f32
+pub const BINARY_MAGIC: &[u8; 4] = b"NBTR";
+pub const BINARY_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum TrajectoryError {
+    #[error("failed to write trajectory `{path}`: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unknown trajectory format `{0}`, expected .csv or .bin")]
+    UnknownFormat(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+// 某一个固定步时所有天体的状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub step: u64,
+    pub time: f32,
+    pub samples: Vec<Sample>,
+}
+
+// 插入这个资源后 NBodyPlugin 开始记录, 移除即停止
+#[derive(Resource, Debug, Clone)]
+pub struct TrajectoryRecorder {
+    // 每 stride 个固定步记录一帧
+    pub stride: u64,
+    capacity: usize,
+    steps: u64,
+    frames: VecDeque<Frame>,
+}
+
+impl TrajectoryRecorder {
+    // capacity 是保留的帧数, 满了以后丢弃最旧的一帧
+    pub fn new(stride: u64, capacity: usize) -> Self {
+        Self {
+            stride: stride.max(1),
+            capacity: capacity.max(1),
+            steps: 0,
+            frames: VecDeque::with_capacity(capacity.max(1)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // 从旧到新
+    pub fn frames(&self) -> impl ExactSizeIterator<Item = &Frame> + DoubleEndedIterator {
+        self.frames.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // 每个固定步调用一次, 到了 stride 才真正记录
+    pub fn step(&mut self, time: f32, samples: impl IntoIterator<Item = Sample>) {
+        let step = self.steps;
+        self.steps += 1;
+        if !step.is_multiple_of(self.stride) {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(Frame {
+            step,
+            time,
+            samples: samples.into_iter().collect(),
+        });
+    }
+
+    // 每个天体每帧一行
+    pub fn write_csv(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "step,time,entity,x,y,z,vx,vy,vz")?;
+        for frame in &self.frames {
+            for sample in &frame.samples {
+                let Sample {
+                    entity,
+                    position: p,
+                    velocity: v,
+                } = sample;
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{},{},{}",
+                    frame.step,
+                    frame.time,
+                    entity.to_bits(),
+                    p.x,
+                    p.y,
+                    p.z,
+                    v.x,
+                    v.y,
+                    v.z,
+                )?;
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn write_binary(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_all(BINARY_MAGIC)?;
+        writer.write_all(&BINARY_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+        for frame in &self.frames {
+            writer.write_all(&frame.step.to_le_bytes())?;
+            writer.write_all(&frame.time.to_le_bytes())?;
+            writer.write_all(&(frame.samples.len() as u32).to_le_bytes())?;
+            for sample in &frame.samples {
+                writer.write_all(&sample.entity.to_bits().to_le_bytes())?;
+                for value in sample.position.to_array() {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                for value in sample.velocity.to_array() {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    // 按扩展名 .csv / .bin 选择格式
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), TrajectoryError> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if !matches!(extension, Some("csv" | "bin")) {
+            return Err(TrajectoryError::UnknownFormat(path.to_path_buf()));
+        }
+        let io_error = |source| TrajectoryError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        let writer = BufWriter::new(File::create(path).map_err(io_error)?);
+        match extension {
+            Some("csv") => self.write_csv(writer),
+            _ => self.write_binary(writer),
+        }
+        .map_err(io_error)
+    }
+}
+
+pub(crate) fn record_trajectories(
+    time: Res<Time>,
+    mut recorder: ResMut<TrajectoryRecorder>,
+    query: Query<(Entity, &Transform, &Velocity)>,
+) {
+    recorder.step(
+        time.elapsed_secs(),
+        query.iter().map(|(entity, transform, velocity)| Sample {
+            entity,
+            position: transform.translation,
+            velocity: velocity.0,
+        }),
+    );
+}
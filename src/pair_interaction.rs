@@ -0,0 +1,420 @@
+//! 短程对势
+//! 只在截断半径内相互作用的两两作用力, 用 cell list 找出邻近的粒子对, O(n) 而不是 iter_combinations 的 O(n²)
+//! 实现 [`PairInteraction`] 就可以接入 Lennard-Jones、弹簧、软排斥等势能
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins((MinimalPlugins, PairInteractionPlugin::<LennardJones>::default()))
+//!     .insert_resource(LennardJones::default())
+//!     // 粒子需要 Transform + PairForce, 力在 PairSystems::Forces 中累加, 积分由使用者负责
+//!     .add_systems(FixedUpdate, integrate.after(PairSystems::Forces))
+//!     .run();
+//! ```
+
+use std::marker::PhantomData;
+
+use bevy::{platform::collections::HashMap, prelude::*};
+
+// 只依赖两点距离的中心力
+// force 为正表示排斥, 负表示吸引, potential 在截断半径处应为 0
+pub trait PairInteraction: Resource {
+    fn cutoff(&self) -> f32;
+    fn force(&self, distance: f32) -> f32;
+    fn potential(&self, distance: f32) -> f32;
+}
+
+// V(r) = 4ε[(σ/r)¹² - (σ/r)⁶], 截断后整体平移使 V(cutoff) = 0
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct LennardJones {
+    pub epsilon: f32,
+    pub sigma: f32,
+    pub cutoff: f32,
+}
+
+impl Default for LennardJones {
+    fn default() -> Self {
+        Self {
+            epsilon: 1.0,
+            sigma: 1.0,
+            cutoff: 2.5,
+        }
+    }
+}
+
+impl LennardJones {
+    fn unshifted(&self, distance: f32) -> f32 {
+        let sr6 = (self.sigma / distance).powi(6);
+        4.0 * self.epsilon * (sr6 * sr6 - sr6)
+    }
+}
+
+impl PairInteraction for LennardJones {
+    fn cutoff(&self) -> f32 {
+        self.cutoff
+    }
+
+    fn force(&self, distance: f32) -> f32 {
+        let sr6 = (self.sigma / distance).powi(6);
+        24.0 * self.epsilon * (2.0 * sr6 * sr6 - sr6) / distance
+    }
+
+    fn potential(&self, distance: f32) -> f32 {
+        self.unshifted(distance) - self.unshifted(self.cutoff)
+    }
+}
+
+// 截断半径内的粒子之间都连着弹簧, 自然长度 rest_length
+// 势能整体平移使 V(cutoff) = 0, 但截断处的力不为 0, 粒子进出截断半径时力会突变, 适合有阻尼的场景
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Spring {
+    pub stiffness: f32,
+    pub rest_length: f32,
+    pub cutoff: f32,
+}
+
+impl PairInteraction for Spring {
+    fn cutoff(&self) -> f32 {
+        self.cutoff
+    }
+
+    fn force(&self, distance: f32) -> f32 {
+        self.stiffness * (self.rest_length - distance)
+    }
+
+    fn potential(&self, distance: f32) -> f32 {
+        0.5 * self.stiffness
+            * ((distance - self.rest_length).powi(2) - (self.cutoff - self.rest_length).powi(2))
+    }
+}
+
+// 重叠时线性排斥, F = strength·(1 - r/radius), 常用于 DPD 和群体避让
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct SoftRepulsion {
+    pub strength: f32,
+    pub radius: f32,
+}
+
+impl PairInteraction for SoftRepulsion {
+    fn cutoff(&self) -> f32 {
+        self.radius
+    }
+
+    fn force(&self, distance: f32) -> f32 {
+        self.strength * (1.0 - distance / self.radius)
+    }
+
+    fn potential(&self, distance: f32) -> f32 {
+        0.5 * self.strength * self.radius * (1.0 - distance / self.radius).powi(2)
+    }
+}
+
+// 13 个"前方"的相邻格子, 加上格子自身, 每对粒子只访问一次
+const HALF_NEIGHBORS: [IVec3; 13] = [
+    IVec3::new(1, 0, 0),
+    IVec3::new(1, 1, 0),
+    IVec3::new(0, 1, 0),
+    IVec3::new(-1, 1, 0),
+    IVec3::new(1, 0, 1),
+    IVec3::new(1, 1, 1),
+    IVec3::new(0, 1, 1),
+    IVec3::new(-1, 1, 1),
+    IVec3::new(1, -1, 1),
+    IVec3::new(0, -1, 1),
+    IVec3::new(-1, -1, 1),
+    IVec3::new(-1, 0, 1),
+    IVec3::new(0, 0, 1),
+];
+
+// 按格子排序的粒子下标, 格子边长不小于截断半径时, 相互作用只发生在相邻格子之间
+// 遍历顺序只取决于粒子位置和下标, 结果可以复现
+#[derive(Debug, Clone)]
+pub struct CellList {
+    cell_size: f32,
+    // 按 (格子, 下标) 排序
+    indices: Vec<usize>,
+    // 格子 -> indices 中的范围
+    cells: HashMap<IVec3, (usize, usize)>,
+    // 有粒子的格子, 按坐标排序
+    order: Vec<IVec3>,
+}
+
+impl CellList {
+    pub fn build(positions: &[Vec3], cell_size: f32) -> Self {
+        let cell_of = |position: Vec3| (position / cell_size).floor().as_ivec3();
+        let mut indices: Vec<usize> = (0..positions.len()).collect();
+        indices.sort_by_key(|&i| (cell_of(positions[i]).to_array(), i));
+
+        let mut cells = HashMap::new();
+        let mut order = Vec::new();
+        let mut start = 0;
+        while start < indices.len() {
+            let cell = cell_of(positions[indices[start]]);
+            let mut end = start + 1;
+            while end < indices.len() && cell_of(positions[indices[end]]) == cell {
+                end += 1;
+            }
+            cells.insert(cell, (start, end));
+            order.push(cell);
+            start = end;
+        }
+
+        Self {
+            cell_size,
+            indices,
+            cells,
+            order,
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell(&self, cell: IVec3) -> &[usize] {
+        self.cells
+            .get(&cell)
+            .map_or(&[], |&(start, end)| &self.indices[start..end])
+    }
+
+    // position 所在格子及周围 26 个格子中的粒子, 按固定顺序遍历
+    // 距离 position 不超过 cell_size 的粒子一定在其中
+    pub fn neighbors(&self, position: Vec3) -> impl Iterator<Item = usize> + '_ {
+        let center = (position / self.cell_size).floor().as_ivec3();
+        (-1..=1)
+            .flat_map(|z| (-1..=1).flat_map(move |y| (-1..=1).map(move |x| IVec3::new(x, y, z))))
+            .flat_map(move |offset| self.cell(center + offset))
+            .copied()
+    }
+
+    // 距离小于 cutoff 的每对粒子调用一次 f(i, j, positions[j] - positions[i], 距离)
+    // cutoff 不能大于 cell_size
+    pub fn for_each_pair(
+        &self,
+        positions: &[Vec3],
+        cutoff: f32,
+        mut f: impl FnMut(usize, usize, Vec3, f32),
+    ) {
+        let cutoff_sq = cutoff * cutoff;
+        let mut visit = |i: usize, j: usize| {
+            let delta = positions[j] - positions[i];
+            let distance_sq = delta.length_squared();
+            if distance_sq < cutoff_sq && distance_sq > 0.0 {
+                f(i, j, delta, distance_sq.sqrt());
+            }
+        };
+
+        for &cell in &self.order {
+            let own = self.cell(cell);
+            for (k, &i) in own.iter().enumerate() {
+                for &j in &own[k + 1..] {
+                    visit(i, j);
+                }
+            }
+            for offset in HALF_NEIGHBORS {
+                for &j in self.cell(cell + offset) {
+                    for &i in own {
+                        visit(i, j);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 不经过 ECS 计算每个粒子受到的力和总势能
+pub fn pair_forces<P: PairInteraction>(interaction: &P, positions: &[Vec3]) -> (Vec<Vec3>, f32) {
+    let cutoff = interaction.cutoff();
+    let mut forces = vec![Vec3::ZERO; positions.len()];
+    let mut potential = 0.0;
+    CellList::build(positions, cutoff).for_each_pair(positions, cutoff, |i, j, delta, distance| {
+        // 排斥力把 i 推向 -delta 方向
+        let force = delta * (interaction.force(distance) / distance);
+        forces[i] -= force;
+        forces[j] += force;
+        potential += interaction.potential(distance);
+    });
+    (forces, potential)
+}
+
+// 这一步累加的力, 使用者积分后需要清零
+#[derive(Component, Debug, Default, Clone, Copy, Deref, DerefMut)]
+pub struct PairForce(pub Vec3);
+
+// 所有对势的总势能, 每个 FixedUpdate 开始时清零
+#[derive(Resource, Debug, Default, Clone, Copy)]
+pub struct PairPotentialEnergy(pub f32);
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PairSystems {
+    Forces,
+}
+
+// 同一个 App 可以添加多个不同 P 的插件, 力和势能会叠加
+pub struct PairInteractionPlugin<P>(PhantomData<P>);
+
+impl<P> Default for PairInteractionPlugin<P> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<P: PairInteraction> Plugin for PairInteractionPlugin<P> {
+    fn build(&self, app: &mut App) {
+        if !app.world().contains_resource::<PairPotentialEnergy>() {
+            app.init_resource::<PairPotentialEnergy>()
+                .add_systems(FixedFirst, reset_potential_energy);
+        }
+        app.add_systems(
+            FixedUpdate,
+            accumulate_pair_forces::<P>
+                .run_if(resource_exists::<P>)
+                .in_set(PairSystems::Forces),
+        );
+    }
+}
+
+fn reset_potential_energy(mut energy: ResMut<PairPotentialEnergy>) {
+    energy.0 = 0.0;
+}
+
+fn accumulate_pair_forces<P: PairInteraction>(
+    interaction: Res<P>,
+    mut energy: ResMut<PairPotentialEnergy>,
+    mut query: Query<(&Transform, &mut PairForce)>,
+) {
+    let positions: Vec<Vec3> = query
+        .iter()
+        .map(|(transform, _)| transform.translation)
+        .collect();
+    let (forces, potential) = pair_forces(&*interaction, &positions);
+    for ((_, mut pair_force), force) in query.iter_mut().zip(forces) {
+        pair_force.0 += force;
+    }
+    energy.0 += potential;
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    // 以原点为中心, 一半粒子落在负坐标的格子里
+    fn random_positions(seed: u64, count: usize, extent: f32) -> Vec<Vec3> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| {
+                Vec3::new(
+                    rng.random_range(-extent..extent),
+                    rng.random_range(-extent..extent),
+                    rng.random_range(-extent..extent),
+                )
+            })
+            .collect()
+    }
+
+    fn brute_force_pairs(positions: &[Vec3], cutoff: f32) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                let distance_sq = positions[i].distance_squared(positions[j]);
+                if distance_sq < cutoff * cutoff && distance_sq > 0.0 {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn cell_list_finds_the_same_pairs_as_brute_force() {
+        let cutoff = 1.0;
+        for (seed, cell_size) in [(1, 1.0), (2, 1.0), (3, 1.7), (4, 3.0)] {
+            let mut positions = random_positions(seed, 500, 4.0);
+            // 正好落在格子边界上的粒子
+            positions.extend([Vec3::ZERO, Vec3::new(0.0, -1.0, 0.5), Vec3::splat(-1.0)]);
+
+            let mut pairs = Vec::new();
+            CellList::build(&positions, cell_size).for_each_pair(
+                &positions,
+                cutoff,
+                |i, j, delta, distance| {
+                    assert_eq!(delta, positions[j] - positions[i]);
+                    assert_eq!(distance, delta.length());
+                    pairs.push((i.min(j), i.max(j)));
+                },
+            );
+            let found = pairs.len();
+            pairs.sort();
+            pairs.dedup();
+            assert_eq!(
+                found,
+                pairs.len(),
+                "cell size {cell_size}: pair visited twice"
+            );
+            assert_eq!(
+                pairs,
+                brute_force_pairs(&positions, cutoff),
+                "cell size {cell_size}"
+            );
+        }
+    }
+
+    // 牛顿第三定律, 所有粒子受到的力之和为 0
+    fn assert_net_force_is_zero(interaction: &impl PairInteraction, positions: &[Vec3]) {
+        let (forces, _) = pair_forces(interaction, positions);
+        let total: Vec3 = forces.iter().sum();
+        let scale: f32 = forces.iter().map(|force| force.length()).sum();
+        assert!(scale > 0.0);
+        assert!(
+            total.length() < 1e-5 * scale,
+            "net force {total}, sum of magnitudes {scale}"
+        );
+    }
+
+    #[test]
+    fn pair_forces_cancel() {
+        let positions = random_positions(5, 500, 4.0);
+        assert_net_force_is_zero(
+            &Spring {
+                stiffness: 2.0,
+                rest_length: 0.5,
+                cutoff: 1.0,
+            },
+            &positions,
+        );
+        assert_net_force_is_zero(
+            &SoftRepulsion {
+                strength: 3.0,
+                radius: 1.0,
+            },
+            &positions,
+        );
+        // Lennard-Jones 在近距离时力非常大, 粒子之间至少相隔 0.8σ
+        let sparse: Vec<Vec3> = positions
+            .iter()
+            .enumerate()
+            .filter(|&(i, p)| positions[..i].iter().all(|q| p.distance(*q) > 0.8))
+            .map(|(_, p)| *p)
+            .collect();
+        assert_net_force_is_zero(&LennardJones::default(), &sparse);
+    }
+
+    #[test]
+    fn potential_vanishes_at_cutoff() {
+        let spring = Spring {
+            stiffness: 2.0,
+            rest_length: 0.5,
+            cutoff: 1.0,
+        };
+        let soft = SoftRepulsion {
+            strength: 3.0,
+            radius: 1.0,
+        };
+        let lj = LennardJones::default();
+        assert_eq!(spring.potential(spring.cutoff()), 0.0);
+        assert_eq!(soft.potential(soft.cutoff()), 0.0);
+        assert_eq!(lj.potential(lj.cutoff()), 0.0);
+    }
+}
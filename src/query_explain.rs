@@ -0,0 +1,348 @@
+//! 解释一个实体为什么(不)匹配某个 QueryData / QueryFilter
+//!
+//! bevy 本身只告诉你"有没有结果",这里把查询拆成一个个条件逐个检查,
+//! 输出类似 "missing ComponentZ" 或 "Added<ComponentC> false since tick 3 (added at tick 1)"
+//!
+//! 内置实现覆盖 With / Without / Added / Changed / Or / 元组 / &T / &mut T / Option 等,
+//! 自定义的 derive(QueryData) / derive(QueryFilter) 结构体用 [`explainable!`] 声明,
+//! 由同一份字段列表生成 [`ExplainTerm`], 修改字段后解释结果不会和查询不一致
+//!
+//! ```ignore
+//! explainable! {
+//!     #[derive(QueryFilter)]
+//!     struct EnemyFilter<T: Component> {
+//!         _enemy: With<Enemy>,
+//!         _or: Or<(Added<Health>, Without<Player>)>,
+//!         _generic: With<T>,
+//!     }
+//! }
+//! ```
+
+use bevy::{
+    ecs::{component::Tick, entity::EntityDoesNotExistError, query::QueryData},
+    prelude::*,
+};
+use std::fmt;
+
+// 检查条件时所需的上下文
+// last_run / this_run 决定 Added 与 Changed 的结果, 在 system 中可以从 SystemChangeTick 获得
+pub struct ExplainContext<'w> {
+    pub entity: EntityRef<'w>,
+    pub last_run: Tick,
+    pub this_run: Tick,
+}
+
+pub trait ExplainTerm {
+    // 不满足的条件写入 failures, 没有写入任何内容表示匹配
+    fn explain(ctx: &ExplainContext, failures: &mut Vec<String>);
+}
+
+#[derive(Debug)]
+pub struct QueryExplanation {
+    pub entity: Entity,
+    pub failures: Vec<String>,
+}
+
+impl QueryExplanation {
+    pub fn is_match(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for QueryExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_match() {
+            return write!(f, "entity {} matches", self.entity);
+        }
+        write!(f, "entity {} does not match:", self.entity)?;
+        for failure in &self.failures {
+            write!(f, "\n  - {failure}")?;
+        }
+        Ok(())
+    }
+}
+
+// 检查 entity 是否能被 Query<D, F> 查询到
+pub fn explain<D: ExplainTerm, F: ExplainTerm>(
+    world: &World,
+    entity: Entity,
+    last_run: Tick,
+    this_run: Tick,
+) -> Result<QueryExplanation, EntityDoesNotExistError> {
+    let ctx = ExplainContext {
+        entity: world.get_entity(entity)?,
+        last_run,
+        this_run,
+    };
+    let mut failures = Vec::new();
+    D::explain(&ctx, &mut failures);
+    F::explain(&ctx, &mut failures);
+    // 同一个组件可能被多个条件要求, 只保留一次
+    let mut seen = Vec::new();
+    failures.retain(|failure| {
+        let first = !seen.contains(failure);
+        seen.push(failure.clone());
+        first
+    });
+    Ok(QueryExplanation { entity, failures })
+}
+
+fn require<T: Component>(ctx: &ExplainContext, failures: &mut Vec<String>) {
+    if !ctx.entity.contains::<T>() {
+        failures.push(format!("missing {}", ShortName::of::<T>()));
+    }
+}
+
+// 原样声明结构体, 并把字段类型按顺序组合成元组实现 ExplainTerm
+// 泛型约束只支持 `T: A + B` 形式, 约束需要是已导入的名字
+#[macro_export]
+macro_rules! explainable {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident $(<$($generic:ident: $bound:ident $(+ $more:ident)*),* $(,)?>)? {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name $(<$($generic: $bound $(+ $more)*),*>)? {
+            $($(#[$field_attr])* $field_vis $field: $ty),*
+        }
+
+        impl $(<$($generic: $bound $(+ $more)*),*>)? $crate::query_explain::ExplainTerm
+            for $name $(<$($generic),*>)?
+        {
+            fn explain(
+                ctx: &$crate::query_explain::ExplainContext,
+                failures: &mut Vec<String>,
+            ) {
+                <($($ty,)*) as $crate::query_explain::ExplainTerm>::explain(ctx, failures);
+            }
+        }
+    };
+}
+
+// ---------- QueryFilter ----------
+
+impl<T: Component> ExplainTerm for With<T> {
+    fn explain(ctx: &ExplainContext, failures: &mut Vec<String>) {
+        require::<T>(ctx, failures);
+    }
+}
+
+impl<T: Component> ExplainTerm for Without<T> {
+    fn explain(ctx: &ExplainContext, failures: &mut Vec<String>) {
+        if ctx.entity.contains::<T>() {
+            failures.push(format!("has {}", ShortName::of::<T>()));
+        }
+    }
+}
+
+impl<T: Component> ExplainTerm for Added<T> {
+    fn explain(ctx: &ExplainContext, failures: &mut Vec<String>) {
+        let Some(ticks) = ctx.entity.get_change_ticks::<T>() else {
+            return require::<T>(ctx, failures);
+        };
+        if !ticks.is_added(ctx.last_run, ctx.this_run) {
+            failures.push(format!(
+                "Added<{}> false since tick {} (added at tick {})",
+                ShortName::of::<T>(),
+                ctx.last_run.get(),
+                ticks.added.get()
+            ));
+        }
+    }
+}
+
+impl<T: Component> ExplainTerm for Changed<T> {
+    fn explain(ctx: &ExplainContext, failures: &mut Vec<String>) {
+        let Some(ticks) = ctx.entity.get_change_ticks::<T>() else {
+            return require::<T>(ctx, failures);
+        };
+        if !ticks.is_changed(ctx.last_run, ctx.this_run) {
+            failures.push(format!(
+                "Changed<{}> false since tick {} (changed at tick {})",
+                ShortName::of::<T>(),
+                ctx.last_run.get(),
+                ticks.changed.get()
+            ));
+        }
+    }
+}
+
+// ---------- QueryData ----------
+
+impl ExplainTerm for () {
+    fn explain(_: &ExplainContext, _: &mut Vec<String>) {}
+}
+
+impl ExplainTerm for Entity {
+    fn explain(_: &ExplainContext, _: &mut Vec<String>) {}
+}
+
+impl ExplainTerm for EntityRef<'_> {
+    fn explain(_: &ExplainContext, _: &mut Vec<String>) {}
+}
+
+impl<T: Component> ExplainTerm for Has<T> {
+    fn explain(_: &ExplainContext, _: &mut Vec<String>) {}
+}
+
+impl<T: Component> ExplainTerm for &T {
+    fn explain(ctx: &ExplainContext, failures: &mut Vec<String>) {
+        require::<T>(ctx, failures);
+    }
+}
+
+impl<T: Component> ExplainTerm for &mut T {
+    fn explain(ctx: &ExplainContext, failures: &mut Vec<String>) {
+        require::<T>(ctx, failures);
+    }
+}
+
+// Option 总是匹配
+impl<T: QueryData> ExplainTerm for Option<T> {
+    fn explain(_: &ExplainContext, _: &mut Vec<String>) {}
+}
+
+// 元组: 所有条件都要满足
+// Or: 只要有一个条件满足, 都不满足时列出每个条件失败的原因
+macro_rules! impl_explain_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: ExplainTerm),*> ExplainTerm for ($($name,)*) {
+            fn explain(ctx: &ExplainContext, failures: &mut Vec<String>) {
+                $($name::explain(ctx, failures);)*
+            }
+        }
+
+        impl<$($name: ExplainTerm),*> ExplainTerm for Or<($($name,)*)> {
+            fn explain(ctx: &ExplainContext, failures: &mut Vec<String>) {
+                let mut reasons = Vec::new();
+                $(
+                    let mut term = Vec::new();
+                    $name::explain(ctx, &mut term);
+                    if term.is_empty() {
+                        return;
+                    }
+                    reasons.push(term.join(", "));
+                )*
+                failures.push(format!("Or<..> none matched: [{}]", reasons.join("; ")));
+            }
+        }
+    };
+}
+
+impl_explain_tuple!(A);
+impl_explain_tuple!(A, B);
+impl_explain_tuple!(A, B, C);
+impl_explain_tuple!(A, B, C, D);
+impl_explain_tuple!(A, B, C, D, E);
+impl_explain_tuple!(A, B, C, D, E, F);
+impl_explain_tuple!(A, B, C, D, E, F, G);
+impl_explain_tuple!(A, B, C, D, E, F, G, H);
+impl_explain_tuple!(A, B, C, D, E, F, G, H, I);
+impl_explain_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_explain_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_explain_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::query::QueryFilter;
+
+    use super::*;
+
+    #[derive(Component)]
+    struct A;
+
+    #[derive(Component)]
+    struct B;
+
+    crate::explainable! {
+        #[derive(QueryFilter)]
+        struct AddedAWithoutB {
+            _added: Added<A>,
+            _without: Without<B>,
+        }
+    }
+
+    // 在 tick t 创建的实体, 对 last_run = t - 1 是新添加的, 对 last_run = t 不是
+    fn failures<D: ExplainTerm, F: ExplainTerm>(
+        world: &World,
+        entity: Entity,
+        last_run: u32,
+    ) -> Vec<String> {
+        explain::<D, F>(world, entity, Tick::new(last_run), world.read_change_tick())
+            .unwrap()
+            .failures
+    }
+
+    #[test]
+    fn with_and_without() {
+        let mut world = World::new();
+        let entity = world.spawn(A).id();
+        assert!(failures::<(), (With<A>, Without<B>)>(&world, entity, 0).is_empty());
+        assert_eq!(
+            failures::<(), (With<B>, Without<A>)>(&world, entity, 0),
+            ["missing B", "has A"]
+        );
+    }
+
+    #[test]
+    fn added_reports_ticks() {
+        let mut world = World::new();
+        world.increment_change_tick();
+        let spawned = world.change_tick().get();
+        let entity = world.spawn(A).id();
+        world.increment_change_tick();
+
+        assert!(failures::<(), Added<A>>(&world, entity, spawned - 1).is_empty());
+        assert_eq!(
+            failures::<(), Added<A>>(&world, entity, spawned),
+            [format!(
+                "Added<A> false since tick {spawned} (added at tick {spawned})"
+            )]
+        );
+        // 没有组件时报告缺少, 而不是 Added 为 false
+        assert_eq!(
+            failures::<(), Added<B>>(&world, entity, spawned),
+            ["missing B"]
+        );
+    }
+
+    #[test]
+    fn or_lists_every_reason() {
+        let mut world = World::new();
+        let entity = world.spawn(A).id();
+        assert!(failures::<(), Or<(With<B>, With<A>)>>(&world, entity, 0).is_empty());
+        assert_eq!(
+            failures::<(), Or<(With<B>, Without<A>)>>(&world, entity, 0),
+            ["Or<..> none matched: [missing B; has A]"]
+        );
+    }
+
+    #[test]
+    fn duplicate_failures_are_reported_once() {
+        let mut world = World::new();
+        let entity = world.spawn(A).id();
+        assert_eq!(
+            failures::<&B, (With<B>, With<B>)>(&world, entity, 0),
+            ["missing B"]
+        );
+    }
+
+    // explainable! 生成的解释与真正的查询结果一致
+    #[test]
+    fn explainable_matches_query() {
+        let mut world = World::new();
+        let fresh = world.spawn(A).id();
+        let blocked = world.spawn((A, B)).id();
+        let mut query = world.query_filtered::<Entity, AddedAWithoutB>();
+        let matched: Vec<Entity> = query.iter(&world).collect();
+        let last_run = world.change_tick().get() - 1;
+        assert_eq!(matched, [fresh]);
+        assert!(failures::<Entity, AddedAWithoutB>(&world, fresh, last_run).is_empty());
+        assert_eq!(
+            failures::<Entity, AddedAWithoutB>(&world, blocked, last_run),
+            ["has B"]
+        );
+    }
+}
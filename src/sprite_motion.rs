@@ -0,0 +1,127 @@
+//! 2D 精灵的移动和越界处理, ch10 和批处理基准测试共用同一份系统
+//! 只处理带 Sprite 的实体, 越界行为由 [`Bounds`] 资源决定, 批处理方式由 [`MotionBatching`] 资源决定
+//!
+//! ```ignore
+//! App::new()
+//!     .init_resource::<Bounds>()
+//!     .init_resource::<MotionBatching>()
+//!     .add_systems(Update, (move_system, bounce_system).chain());
+//! ```
+
+use bevy::{ecs::batching::BatchingStrategy, prelude::*};
+
+use crate::bounds::Bounds;
+
+// 速度, 每帧移动的像素
+#[derive(Component, Debug, Default, Clone, Copy, Deref, DerefMut)]
+pub struct Velocity(pub Vec2);
+
+// move_system 和 bounce_system 的批处理方式, None 时用 iter_mut 单线程执行
+#[derive(Resource, Debug, Clone)]
+pub struct MotionBatching(pub Option<BatchingStrategy>);
+
+impl Default for MotionBatching {
+    // 更改任务的颗粒度 N个任务合并执行
+    fn default() -> Self {
+        Self(Some(BatchingStrategy::fixed(32)))
+    }
+}
+
+// 移动
+pub fn move_system(
+    batching: Res<MotionBatching>,
+    mut query: Query<(&mut Transform, &Velocity), With<Sprite>>,
+) {
+    let step = |(mut transform, velocity): (Mut<Transform>, &Velocity)| {
+        transform.translation += velocity.extend(0.0);
+    };
+    match &batching.0 {
+        Some(batching) => query
+            .par_iter_mut()
+            .batching_strategy(batching.clone())
+            .for_each(step),
+        None => query.iter_mut().for_each(step),
+    }
+}
+
+// 反弹
+// 当 entity 超出边界时, 按 Bounds 的模式处理, Absorb 时删除
+pub fn bounce_system(
+    bounds: Res<Bounds>,
+    batching: Res<MotionBatching>,
+    mut sprites: Query<(Entity, &mut Transform, &mut Velocity), With<Sprite>>,
+    par_commands: ParallelCommands,
+) {
+    let bounce =
+        |(entity, mut transform, mut velocity): (Entity, Mut<Transform>, Mut<Velocity>)| {
+            let mut position = transform.translation.truncate();
+            if !bounds.apply(&mut position, &mut velocity.0) {
+                par_commands.command_scope(|mut commands| commands.entity(entity).despawn());
+                return;
+            }
+            // 没有越界时不触发 Transform 的变更检测
+            if position != transform.translation.truncate() {
+                transform.translation = position.extend(transform.translation.z);
+            }
+        };
+    match &batching.0 {
+        Some(batching) => sprites
+            .par_iter_mut()
+            .batching_strategy(batching.clone())
+            .for_each(bounce),
+        None => sprites.iter_mut().for_each(bounce),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounds::BoundaryMode;
+
+    fn run(mode: BoundaryMode, batching: Option<BatchingStrategy>) -> (World, [Entity; 3]) {
+        let mut world = World::new();
+        world.insert_resource(Bounds { mode, ..default() });
+        world.insert_resource(MotionBatching(batching));
+        let inside = world
+            .spawn((Sprite::default(), Transform::default(), Velocity(Vec2::ONE)))
+            .id();
+        let leaving = world
+            .spawn((
+                Sprite::default(),
+                Transform::from_xyz(639.0, 0.0, 5.0),
+                Velocity(Vec2::new(4.0, 0.0)),
+            ))
+            .id();
+        // 不是精灵, 不受影响
+        let other = world
+            .spawn((
+                Transform::from_xyz(639.0, 0.0, 0.0),
+                Velocity(Vec2::X * 4.0),
+            ))
+            .id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((move_system, bounce_system).chain());
+        schedule.run(&mut world);
+        (world, [inside, leaving, other])
+    }
+
+    #[test]
+    fn moves_and_bounces_sprites_only() {
+        for batching in [None, Some(BatchingStrategy::fixed(1))] {
+            let (world, [inside, leaving, other]) = run(BoundaryMode::Reflect, batching.clone());
+            let translation = |entity| world.get::<Transform>(entity).unwrap().translation;
+            assert_eq!(translation(inside), Vec3::new(1.0, 1.0, 0.0));
+            assert_eq!(translation(leaving), Vec3::new(640.0, 0.0, 5.0));
+            assert_eq!(
+                world.get::<Velocity>(leaving).unwrap().0,
+                Vec2::new(-4.0, 0.0)
+            );
+            assert_eq!(translation(other), Vec3::new(639.0, 0.0, 0.0));
+
+            let (world, [inside, leaving, other]) = run(BoundaryMode::Absorb, batching);
+            assert!(world.get_entity(inside).is_ok());
+            assert!(world.get_entity(leaving).is_err());
+            assert!(world.get_entity(other).is_ok());
+        }
+    }
+}
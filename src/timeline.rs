@@ -0,0 +1,372 @@
+//! 按游戏时间执行一次性系统的时间线
+//! 条目写成 "2 秒时删除 root/blue", 代替在系统中手动比较 elapsed_secs
+//! 时间取自 Time<Virtual>, 暂停或变速时时间线一起暂停或变速
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins((DefaultPlugins, TimelinePlugin))
+//!     .add_systems(Startup, |mut timeline: ResMut<Timeline>| {
+//!         // 游戏时间 2 秒时
+//!         timeline.at(Duration::from_secs(2), || info!("two seconds"));
+//!         // 从现在起每 0.5 秒一次, 共 3 次
+//!         let id = timeline.every(Duration::from_millis(500), Some(3), || info!("tick"));
+//!         // 还没执行完的条目可以取消
+//!         timeline.cancel(id);
+//!     });
+//! ```
+
+use std::time::Duration;
+
+use bevy::{ecs::system::BoxedSystem, prelude::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimelineId(u64);
+
+// 循环执行, count 是总执行次数, None 表示无限循环
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repeat {
+    pub interval: Duration,
+    pub count: Option<u32>,
+}
+
+struct Entry {
+    id: TimelineId,
+    // Time<Virtual>::elapsed 达到这个值时执行
+    next: Duration,
+    interval: Duration,
+    // 剩余的执行次数
+    remaining: Option<u32>,
+    // 执行期间被取出
+    system: Option<BoxedSystem>,
+    initialized: bool,
+}
+
+// 每次更新每个条目最多执行一次, 同一次更新中按预定时间的先后执行
+// 间隔小于帧时间的循环会逐帧补上落下的次数
+#[derive(Resource, Default)]
+pub struct Timeline {
+    entries: Vec<Entry>,
+    next_id: u64,
+    // 本次更新的 Time<Virtual>::elapsed, 相对时间从这里算起
+    now: Duration,
+}
+
+impl Timeline {
+    // 游戏时间为 time 时执行一次, time 已经过去时在下一次更新时执行
+    pub fn at<M>(&mut self, time: Duration, system: impl IntoSystem<(), (), M>) -> TimelineId {
+        self.add(time, None, system)
+    }
+
+    // 从现在起 delay 之后执行一次
+    pub fn after<M>(&mut self, delay: Duration, system: impl IntoSystem<(), (), M>) -> TimelineId {
+        self.add(self.now + delay, None, system)
+    }
+
+    // 从现在起每隔 interval 执行一次
+    pub fn every<M>(
+        &mut self,
+        interval: Duration,
+        count: Option<u32>,
+        system: impl IntoSystem<(), (), M>,
+    ) -> TimelineId {
+        self.add(
+            self.now + interval,
+            Some(Repeat { interval, count }),
+            system,
+        )
+    }
+
+    // 第一次在游戏时间 start 执行, 之后按 repeat 循环
+    pub fn add<M>(
+        &mut self,
+        start: Duration,
+        repeat: Option<Repeat>,
+        system: impl IntoSystem<(), (), M>,
+    ) -> TimelineId {
+        let id = TimelineId(self.next_id);
+        self.next_id += 1;
+        let (interval, remaining) = match repeat {
+            Some(repeat) => (repeat.interval, repeat.count),
+            None => (Duration::ZERO, Some(1)),
+        };
+        if remaining != Some(0) {
+            self.entries.push(Entry {
+                id,
+                next: start,
+                interval,
+                remaining,
+                system: Some(Box::new(IntoSystem::into_system(system))),
+                initialized: false,
+            });
+        }
+        id
+    }
+
+    // 返回条目是否还在等待执行, 正在执行的条目取消自己后不会再循环
+    pub fn cancel(&mut self, id: TimelineId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    pub fn contains(&self, id: TimelineId) -> bool {
+        self.entries.iter().any(|entry| entry.id == id)
+    }
+
+    // 条目下一次执行的游戏时间
+    pub fn next_time(&self, id: TimelineId) -> Option<Duration> {
+        self.entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| entry.next)
+    }
+
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // 取出所有到期条目的系统, 按 (预定时间, id) 排序
+    fn take_due(&mut self, now: Duration) -> Vec<(TimelineId, BoxedSystem, bool)> {
+        self.now = now;
+        let mut due: Vec<(Duration, TimelineId, BoxedSystem, bool)> = self
+            .entries
+            .iter_mut()
+            .filter(|entry| entry.next <= now)
+            .filter_map(|entry| {
+                let system = entry.system.take()?;
+                let initialized = std::mem::replace(&mut entry.initialized, true);
+                Some((entry.next, entry.id, system, initialized))
+            })
+            .collect();
+        due.sort_by_key(|(next, id, ..)| (*next, *id));
+        due.into_iter()
+            .map(|(_, id, system, initialized)| (id, system, initialized))
+            .collect()
+    }
+
+    // 放回执行过的系统, 已取消或次数用完的条目直接丢弃
+    fn finish(&mut self, id: TimelineId, system: BoxedSystem) {
+        let Some(index) = self.entries.iter().position(|entry| entry.id == id) else {
+            return;
+        };
+        let entry = &mut self.entries[index];
+        if let Some(remaining) = &mut entry.remaining {
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.entries.remove(index);
+                return;
+            }
+        }
+        entry.next += entry.interval;
+        entry.system = Some(system);
+    }
+}
+
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        // 在 PreUpdate 执行, Update 中的系统看到的是本帧时间线执行之后的世界
+        app.init_resource::<Timeline>()
+            .add_systems(PreUpdate, run_timeline);
+    }
+}
+
+// 执行系统时 Timeline 仍在世界中, 系统可以添加或取消条目
+fn run_timeline(world: &mut World) {
+    let now = world.resource::<Time<Virtual>>().elapsed();
+    let due = world.resource_mut::<Timeline>().take_due(now);
+    for (id, mut system, initialized) in due {
+        // 被同一次更新中先执行的条目取消了
+        if !world.resource::<Timeline>().contains(id) {
+            continue;
+        }
+        if !initialized {
+            system.initialize(world);
+        }
+        if let Err(err) = system.run((), world) {
+            warn!("timeline entry {id:?} ({}) failed: {err}", system.name());
+        }
+        world.resource_mut::<Timeline>().finish(id, system);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    // 执行过的条目和执行时的游戏时间 (毫秒)
+    #[derive(Resource, Default)]
+    struct Log(Vec<(&'static str, u64)>);
+
+    fn record(label: &'static str) -> impl FnMut(ResMut<Log>, Res<Time<Virtual>>) {
+        move |mut log, time| log.0.push((label, time.elapsed().as_millis() as u64))
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // 第一次更新的 delta 为 0, 之后每次 advance 正好前进给定的时间
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TimelinePlugin))
+            .init_resource::<Log>();
+        app.update();
+        app
+    }
+
+    fn advance(app: &mut App, delta: Duration) {
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(delta));
+        app.update();
+    }
+
+    fn timeline(app: &mut App) -> Mut<'_, Timeline> {
+        app.world_mut().resource_mut::<Timeline>()
+    }
+
+    // 取出并清空记录
+    fn log(app: &mut App) -> Vec<(&'static str, u64)> {
+        std::mem::take(&mut app.world_mut().resource_mut::<Log>().0)
+    }
+
+    #[test]
+    fn absolute_and_relative_times() {
+        let mut app = app();
+        timeline(&mut app).at(ms(250), record("at"));
+        timeline(&mut app).after(ms(100), record("after"));
+        // 相对时间从执行时算起
+        timeline(&mut app).at(ms(100), |mut timeline: ResMut<Timeline>| {
+            timeline.after(ms(150), record("nested"));
+        });
+        for _ in 0..4 {
+            advance(&mut app, ms(100));
+        }
+        // at 和 nested 都预定在 250, 同一次更新中按添加的先后执行
+        assert_eq!(
+            log(&mut app),
+            [("after", 100), ("at", 300), ("nested", 300)]
+        );
+        // 已经过去的时间在下一次更新时执行
+        timeline(&mut app).at(ms(50), record("late"));
+        advance(&mut app, ms(100));
+        assert_eq!(log(&mut app), [("late", 500)]);
+        assert!(timeline(&mut app).is_empty());
+    }
+
+    #[test]
+    fn every_runs_count_times() {
+        let mut app = app();
+        let id = timeline(&mut app).every(ms(100), Some(3), record("tick"));
+        assert_eq!(timeline(&mut app).next_time(id), Some(ms(100)));
+        for _ in 0..5 {
+            advance(&mut app, ms(100));
+        }
+        assert_eq!(log(&mut app), [("tick", 100), ("tick", 200), ("tick", 300)]);
+        assert!(!timeline(&mut app).contains(id));
+        // 0 次的循环不会加入时间线
+        let id = timeline(&mut app).every(ms(100), Some(0), record("never"));
+        assert!(!timeline(&mut app).contains(id));
+    }
+
+    // 一帧跨过多个间隔时每次更新只执行一次, 之后逐帧补上
+    #[test]
+    fn every_catches_up_after_long_frame() {
+        let mut app = app();
+        let id = timeline(&mut app).every(ms(50), None, record("tick"));
+        advance(&mut app, ms(200));
+        assert_eq!(log(&mut app), [("tick", 200)]);
+        assert_eq!(timeline(&mut app).next_time(id), Some(ms(100)));
+        for _ in 0..4 {
+            advance(&mut app, Duration::ZERO);
+        }
+        assert_eq!(log(&mut app), [("tick", 200); 3]);
+        assert_eq!(timeline(&mut app).next_time(id), Some(ms(250)));
+        advance(&mut app, ms(50));
+        assert_eq!(log(&mut app), [("tick", 250)]);
+    }
+
+    #[derive(Resource)]
+    struct Target(TimelineId);
+
+    #[test]
+    fn cancel_from_own_system() {
+        let mut app = app();
+        // 第二次执行时取消自己, 此时条目的系统已经被 take_due 取出
+        let id = timeline(&mut app).every(
+            ms(100),
+            None,
+            |mut log: ResMut<Log>, target: Res<Target>, mut timeline: ResMut<Timeline>| {
+                log.0.push(("tick", timeline.now().as_millis() as u64));
+                if log.0.len() == 2 {
+                    assert!(timeline.cancel(target.0));
+                }
+            },
+        );
+        app.insert_resource(Target(id));
+        for _ in 0..4 {
+            advance(&mut app, ms(100));
+        }
+        assert_eq!(log(&mut app), [("tick", 100), ("tick", 200)]);
+        assert!(!timeline(&mut app).contains(id));
+        assert!(!timeline(&mut app).cancel(id));
+    }
+
+    #[test]
+    fn cancel_later_entry_in_same_update() {
+        let mut app = app();
+        let later = timeline(&mut app).at(ms(100), record("later"));
+        app.insert_resource(Target(later));
+        // 预定时间更早, 同一次更新中先执行
+        timeline(&mut app).at(
+            ms(50),
+            |target: Res<Target>, mut timeline: ResMut<Timeline>| {
+                timeline.cancel(target.0);
+            },
+        );
+        let kept = timeline(&mut app).at(ms(100), record("kept"));
+        advance(&mut app, ms(100));
+        assert_eq!(log(&mut app), [("kept", 100)]);
+        assert!(!timeline(&mut app).contains(later));
+        assert!(!timeline(&mut app).contains(kept));
+    }
+
+    #[test]
+    fn follows_virtual_time() {
+        let mut app = app();
+        timeline(&mut app).at(ms(200), record("at"));
+        advance(&mut app, ms(100));
+        app.world_mut().resource_mut::<Time<Virtual>>().pause();
+        for _ in 0..5 {
+            advance(&mut app, ms(100));
+        }
+        assert!(log(&mut app).is_empty());
+        assert_eq!(timeline(&mut app).now(), ms(100));
+
+        // 恢复后以 2 倍速前进
+        let mut time = app.world_mut().resource_mut::<Time<Virtual>>();
+        time.unpause();
+        time.set_relative_speed(2.0);
+        advance(&mut app, ms(50));
+        assert_eq!(log(&mut app), [("at", 200)]);
+        timeline(&mut app).after(ms(400), record("after"));
+        advance(&mut app, ms(100));
+        assert!(log(&mut app).is_empty());
+        advance(&mut app, ms(100));
+        assert_eq!(log(&mut app), [("after", 600)]);
+    }
+}
@@ -0,0 +1,411 @@
+//! Transform 关键帧动画
+//! 片段由若干关键帧组成, 每段用 EaseFunction 插值 translation、rotation、scale
+//! 动画写入的是局部 Transform, 父子实体各自播放自己的片段, 效果按层次结构叠加
+//! sample 只依赖播放时间, 相同的时间总是得到相同的 Transform, 不需要窗口就能检查
+//!
+//! ```ignore
+//! let clip = TransformClip::new(ClipMode::Loop)
+//!     .keyframe(0.0, Transform::IDENTITY, EaseFunction::QuadraticInOut)
+//!     .keyframe(1.0, Transform::from_xyz(100.0, 0.0, 0.0), EaseFunction::QuadraticInOut)
+//!     .keyframe(2.0, Transform::IDENTITY, EaseFunction::Linear);
+//! commands.spawn((Sprite::default(), TransformAnimation::new(clip)));
+//!
+//! // 每播放完一遍触发一次
+//! app.add_observer(|ended: On<ClipEnded>| info!("{} cycle {}", ended.entity, ended.cycle));
+//! ```
+
+use bevy::prelude::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum ClipMode {
+    // 播放一遍后停在最后一帧
+    #[default]
+    Once,
+    // 从头循环
+    Loop,
+    // 正放、倒放交替, 一轮包含一次正放和一次倒放
+    PingPong,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct Keyframe {
+    // 片段中的时间, 秒
+    pub time: f32,
+    pub transform: Transform,
+    // 从这一帧到下一帧的缓动函数
+    pub easing: EaseFunction,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Reflect)]
+pub struct TransformClip {
+    // 按时间排序
+    keyframes: Vec<Keyframe>,
+    pub mode: ClipMode,
+}
+
+impl TransformClip {
+    pub fn new(mode: ClipMode) -> Self {
+        Self {
+            keyframes: Vec::new(),
+            mode,
+        }
+    }
+
+    // 时间相同的关键帧按添加顺序排列, 可以用来做突变
+    pub fn keyframe(mut self, time: f32, transform: Transform, easing: EaseFunction) -> Self {
+        let index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        self.keyframes.insert(
+            index,
+            Keyframe {
+                time,
+                transform,
+                easing,
+            },
+        );
+        self
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    // 最后一帧的时间, 第一帧之前保持第一帧的姿态
+    pub fn duration(&self) -> f32 {
+        self.keyframes
+            .last()
+            .map_or(0.0, |keyframe| keyframe.time.max(0.0))
+    }
+
+    // 一轮的长度, PingPong 是 duration 的两倍
+    pub fn period(&self) -> f32 {
+        match self.mode {
+            ClipMode::PingPong => 2.0 * self.duration(),
+            ClipMode::Once | ClipMode::Loop => self.duration(),
+        }
+    }
+
+    // 播放了 time 秒时片段中的位置
+    pub fn local_time(&self, time: f32) -> f32 {
+        let duration = self.duration();
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        match self.mode {
+            ClipMode::Once => time.clamp(0.0, duration),
+            ClipMode::Loop => time.rem_euclid(duration),
+            ClipMode::PingPong => {
+                let time = time.rem_euclid(2.0 * duration);
+                if time > duration {
+                    2.0 * duration - time
+                } else {
+                    time
+                }
+            }
+        }
+    }
+
+    // 播放了 time 秒时的 Transform, 没有关键帧时为 None
+    pub fn sample(&self, time: f32) -> Option<Transform> {
+        let time = self.local_time(time);
+        let index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        let (from, to) = match index {
+            0 => return self.keyframes.first().map(|keyframe| keyframe.transform),
+            i if i == self.keyframes.len() => return Some(self.keyframes[i - 1].transform),
+            i => (self.keyframes[i - 1], self.keyframes[i]),
+        };
+        let t = (time - from.time) / (to.time - from.time);
+        let t = from.easing.sample_clamped(t);
+        Some(Transform {
+            translation: from.transform.translation.lerp(to.transform.translation, t),
+            rotation: from.transform.rotation.slerp(to.transform.rotation, t),
+            scale: from.transform.scale.lerp(to.transform.scale, t),
+        })
+    }
+}
+
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+#[require(Transform)]
+pub struct TransformAnimation {
+    pub clip: TransformClip,
+    // 当前一轮中播放了多少秒
+    pub elapsed: f32,
+    // 已经播放完的轮数
+    pub cycles: u32,
+    // 播放速度倍数, 小于 0 时按 0 处理
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl TransformAnimation {
+    pub fn new(clip: TransformClip) -> Self {
+        Self {
+            clip,
+            elapsed: 0.0,
+            cycles: 0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    // 只有 Once 会结束
+    pub fn is_finished(&self) -> bool {
+        self.clip.mode == ClipMode::Once && self.cycles > 0
+    }
+
+    // 前进 dt 秒, 返回这段时间内播放完的轮数
+    // Loop 和 PingPong 的 elapsed 保持在一轮之内, 长时间播放也不会损失精度
+    pub fn advance(&mut self, dt: f32) -> u32 {
+        if self.paused || self.is_finished() {
+            return 0;
+        }
+        let period = self.clip.period();
+        self.elapsed += dt * self.speed.max(0.0);
+        if self.elapsed < period {
+            return 0;
+        }
+        let completed = match self.clip.mode {
+            ClipMode::Once => {
+                self.elapsed = period;
+                1
+            }
+            // 长度为 0 的循环片段没有意义, 不计轮数
+            _ if period <= 0.0 => {
+                self.elapsed = 0.0;
+                0
+            }
+            ClipMode::Loop | ClipMode::PingPong => {
+                let completed = (self.elapsed / period).floor();
+                self.elapsed -= completed * period;
+                completed as u32
+            }
+        };
+        self.cycles += completed;
+        completed
+    }
+
+    pub fn sample(&self) -> Option<Transform> {
+        self.clip.sample(self.elapsed)
+    }
+}
+
+// 片段播放完一轮时触发, Once 只触发一次, 一帧内播放完多轮时每轮触发一次
+#[derive(EntityEvent, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClipEnded {
+    pub entity: Entity,
+    // 播放完的是第几轮, 从 1 开始
+    pub cycle: u32,
+}
+
+pub struct TransformAnimationPlugin;
+
+impl Plugin for TransformAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TransformAnimation>()
+            .add_systems(Update, animate_transforms);
+    }
+}
+
+// 使用 Time<Virtual>, 游戏暂停时动画也暂停
+fn animate_transforms(
+    time: Res<Time>,
+    mut animations: Query<(Entity, &mut TransformAnimation, &mut Transform)>,
+    mut commands: Commands,
+) {
+    let dt = time.delta_secs();
+    for (entity, mut animation, mut transform) in &mut animations {
+        if animation.paused || animation.is_finished() {
+            continue;
+        }
+        let completed = animation.advance(dt);
+        for cycle in animation.cycles - completed + 1..=animation.cycles {
+            commands.trigger(ClipEnded { entity, cycle });
+        }
+        if let Some(sampled) = animation.sample() {
+            *transform = sampled;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    const A: Transform = Transform::IDENTITY;
+    const B: Transform = Transform::from_xyz(10.0, 0.0, 0.0);
+    const C: Transform = Transform::from_xyz(10.0, 20.0, 0.0);
+
+    // 0 秒 A, 1 秒 B, 2 秒 C, 都是线性插值
+    fn clip(mode: ClipMode) -> TransformClip {
+        TransformClip::new(mode)
+            .keyframe(2.0, C, EaseFunction::Linear)
+            .keyframe(0.0, A, EaseFunction::Linear)
+            .keyframe(1.0, B, EaseFunction::Linear)
+    }
+
+    fn translation(clip: &TransformClip, time: f32) -> Vec3 {
+        clip.sample(time).unwrap().translation
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!(actual.abs_diff_eq(expected, 1e-4), "{actual} vs {expected}");
+    }
+
+    #[test]
+    fn samples_keyframes_and_interpolates() {
+        let clip = clip(ClipMode::Once);
+        assert_eq!(clip.duration(), 2.0);
+        // 关键帧按时间排序
+        let times: Vec<f32> = clip.keyframes().iter().map(|k| k.time).collect();
+        assert_eq!(times, [0.0, 1.0, 2.0]);
+
+        assert_near(translation(&clip, 0.0), A.translation);
+        assert_near(translation(&clip, 1.0), B.translation);
+        assert_near(translation(&clip, 2.0), C.translation);
+        assert_near(translation(&clip, 0.25), Vec3::new(2.5, 0.0, 0.0));
+        assert_near(translation(&clip, 1.5), Vec3::new(10.0, 10.0, 0.0));
+
+        // 缓动函数作用在段内的比例上
+        let eased = TransformClip::new(ClipMode::Once)
+            .keyframe(0.0, A, EaseFunction::QuadraticIn)
+            .keyframe(2.0, B, EaseFunction::Linear);
+        assert_near(translation(&eased, 1.0), Vec3::new(2.5, 0.0, 0.0));
+
+        assert_eq!(TransformClip::new(ClipMode::Loop).sample(1.0), None);
+    }
+
+    #[test]
+    fn once_clamps() {
+        let clip = clip(ClipMode::Once);
+        assert_near(translation(&clip, -1.0), A.translation);
+        assert_near(translation(&clip, 5.0), C.translation);
+
+        let mut animation = TransformAnimation::new(clip);
+        assert_eq!(animation.advance(1.5), 0);
+        assert_eq!(animation.advance(1.5), 1);
+        assert!(animation.is_finished());
+        assert_eq!(animation.elapsed, 2.0);
+        // 结束后不再前进, 也不再计轮数
+        assert_eq!(animation.advance(10.0), 0);
+        assert_eq!(animation.cycles, 1);
+        assert_near(animation.sample().unwrap().translation, C.translation);
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let clip = clip(ClipMode::Loop);
+        assert_eq!(clip.period(), 2.0);
+        assert_near(translation(&clip, 2.25), translation(&clip, 0.25));
+        assert_near(translation(&clip, 7.5), translation(&clip, 1.5));
+        assert_near(translation(&clip, -0.5), translation(&clip, 1.5));
+
+        let mut animation = TransformAnimation::new(clip);
+        assert_eq!(animation.advance(1.5), 0);
+        assert_eq!(animation.advance(5.0), 3);
+        assert_eq!(animation.cycles, 3);
+        assert!((animation.elapsed - 0.5).abs() < 1e-5);
+        assert!(!animation.is_finished());
+    }
+
+    #[test]
+    fn ping_pong_plays_backwards() {
+        let clip = clip(ClipMode::PingPong);
+        assert_eq!(clip.period(), 4.0);
+        // 2..4 秒倒放
+        assert_near(translation(&clip, 2.5), translation(&clip, 1.5));
+        assert_near(translation(&clip, 3.0), B.translation);
+        assert_near(translation(&clip, 4.0), A.translation);
+        assert_near(translation(&clip, 4.25), translation(&clip, 0.25));
+        assert_near(translation(&clip, 6.5), translation(&clip, 1.5));
+
+        let mut animation = TransformAnimation::new(clip).with_speed(2.0);
+        assert_eq!(animation.advance(1.0), 0);
+        assert_near(animation.sample().unwrap().translation, C.translation);
+        assert_eq!(animation.advance(1.5), 1);
+        assert_near(animation.sample().unwrap().translation, B.translation);
+    }
+
+    #[derive(Resource, Default)]
+    struct Ended(Vec<(Entity, u32)>);
+
+    // 每帧前进 0.1 秒
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformAnimationPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .init_resource::<Ended>()
+            .add_observer(|ended: On<ClipEnded>, mut log: ResMut<Ended>| {
+                log.0.push((ended.entity, ended.cycle));
+            });
+        // 第一帧时间不前进
+        app.update();
+        app
+    }
+
+    fn run(app: &mut App, frames: usize) -> Vec<(Entity, u32)> {
+        for _ in 0..frames {
+            app.update();
+        }
+        std::mem::take(&mut app.world_mut().resource_mut::<Ended>().0)
+    }
+
+    #[test]
+    fn clip_ended_fires_once_per_cycle() {
+        let mut app = app();
+        let looping = app
+            .world_mut()
+            .spawn(TransformAnimation::new(clip(ClipMode::Loop)))
+            .id();
+        let once = app
+            .world_mut()
+            .spawn(TransformAnimation::new(clip(ClipMode::Once)))
+            .id();
+
+        // 1.9 秒, 还没有播放完一轮
+        assert_eq!(run(&mut app, 19), []);
+        // 2.1 秒
+        let mut ended = run(&mut app, 2);
+        ended.sort();
+        let mut expected = vec![(looping, 1), (once, 1)];
+        expected.sort();
+        assert_eq!(ended, expected);
+        // 4.1 秒, Once 不再触发
+        assert_eq!(run(&mut app, 20), [(looping, 2)]);
+        assert_eq!(
+            app.world().get::<Transform>(once).unwrap().translation,
+            C.translation
+        );
+    }
+
+    // 一帧内播放完多轮时每轮触发一次, 轮数按顺序递增
+    #[test]
+    fn clip_ended_fires_for_every_cycle_in_a_frame() {
+        let mut app = app();
+        let fast = app
+            .world_mut()
+            .spawn(TransformAnimation::new(clip(ClipMode::PingPong)).with_speed(100.0))
+            .id();
+        // 一帧前进 10 秒, 一轮 4 秒
+        assert_eq!(run(&mut app, 1), [(fast, 1), (fast, 2)]);
+        assert_eq!(run(&mut app, 1), [(fast, 3), (fast, 4), (fast, 5)]);
+        assert_eq!(
+            app.world().get::<TransformAnimation>(fast).unwrap().cycles,
+            5
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! 不打开窗口运行 NBodyPlugin, 检查轨道和守恒量
+//! 每次 update 手动前进一个固定步长, 正好执行一次 FixedUpdate
+
+use std::{f32::consts::TAU, time::Duration};
+
+use bevy::{diagnostic::DiagnosticsStore, prelude::*, time::TimeUpdateStrategy};
+use blibli_bevy2::nbody::{
+    BodySpec, GRAVITY_CONSTANT, Integrator, Mass, NBodyPlugin, OrbitPrediction, Preset, SUBSTEPS,
+    Scenario, Velocity, generate_bodies,
+};
+
+fn headless_app(scenario: Scenario, integrator: Integrator) -> App {
+    let mut app = App::new();
+    let timestep = app
+        .add_plugins((MinimalPlugins, NBodyPlugin))
+        .world()
+        .resource::<Time<Fixed>>()
+        .timestep();
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+        .insert_resource(scenario)
+        .insert_resource(integrator)
+        .add_systems(Startup, generate_bodies);
+    app
+}
+
+fn timestep(app: &App) -> f32 {
+    app.world()
+        .resource::<Time<Fixed>>()
+        .timestep()
+        .as_secs_f32()
+}
+
+fn fixed_elapsed(app: &App) -> Duration {
+    app.world().resource::<Time<Fixed>>().elapsed()
+}
+
+// 每个天体的 (质量, 位置, 速度), 按质量从大到小排序
+fn bodies(app: &mut App) -> Vec<(f32, Vec3, Vec3)> {
+    let world = app.world_mut();
+    let mut bodies: Vec<_> = world
+        .query::<(&Mass, &Transform, &Velocity)>()
+        .iter(world)
+        .map(|(mass, transform, velocity)| (mass.0, transform.translation, velocity.0))
+        .collect();
+    bodies.sort_by(|a, b| b.0.total_cmp(&a.0));
+    bodies
+}
+
+fn body(mass: f32, position: Vec3, velocity: Vec3) -> BodySpec {
+    BodySpec {
+        mass,
+        position,
+        velocity,
+        radius: 0.2,
+        color: [1.0; 3],
+        star: false,
+    }
+}
+
+const ORBITS: u32 = 2;
+const SEPARATION: f32 = 4.0;
+
+// 质量不同的两个天体在 XZ 平面上做圆轨道运动
+// 引力大小为 G·m1·m2/r, 相对运动的圆轨道速度为 √(G·(m1 + m2)), 周期为 2π·r / v
+fn two_body_scenario() -> (Scenario, f32) {
+    let (m1, m2) = (300.0, 100.0);
+    let speed = ops::sqrt(GRAVITY_CONSTANT * (m1 + m2));
+    let scenario = Scenario {
+        bodies: vec![
+            // 质心和总动量都为 0
+            body(
+                m1,
+                Vec3::X * SEPARATION * m2 / (m1 + m2),
+                Vec3::NEG_Z * speed * m2 / (m1 + m2),
+            ),
+            body(
+                m2,
+                Vec3::NEG_X * SEPARATION * m1 / (m1 + m2),
+                Vec3::Z * speed * m1 / (m1 + m2),
+            ),
+        ],
+    };
+    (scenario, TAU * SEPARATION / speed)
+}
+
+// 从第一个天体指向第二个天体
+fn separation(app: &mut App) -> Vec3 {
+    let bodies = bodies(app);
+    bodies[1].1 - bodies[0].1
+}
+
+fn check_two_body_orbit(integrator: Integrator) {
+    let (scenario, period) = two_body_scenario();
+    let mut app = headless_app(scenario, integrator);
+    app.update();
+    let start = separation(&mut app);
+    let start_time = fixed_elapsed(&app);
+
+    // 累计相对位置转过的角度, 转满 ORBITS 圈时按线性插值得到周期
+    // 每步只转过很小的角度, Vec2::angle_to 基于 acos, 小角度时误差太大, 这里用 atan2
+    // 累加几千次, 用 f64 避免舍入误差超过要检查的周期误差
+    let mut angle = 0.0;
+    let mut previous = start;
+    let (mut min_distance, mut max_distance) = (f32::INFINITY, 0.0f32);
+    let (mut measured, mut end) = (None, start);
+    // 多走 1% 的步数, 数值周期略长时也能测到
+    let steps = (ORBITS as f32 * period / timestep(&app)).round() as u32;
+    for step in 1..=steps + steps / 100 {
+        app.update();
+        let current = separation(&mut app);
+        if step == steps {
+            end = current;
+        }
+        let (previous_xz, current_xz) = (previous.xz(), current.xz());
+        let turned = ops::atan2(
+            previous_xz.perp_dot(current_xz),
+            previous_xz.dot(current_xz),
+        )
+        .abs() as f64;
+        let target = ORBITS as f64 * TAU as f64;
+        if measured.is_none() && angle + turned >= target {
+            let fraction = ((target - angle) / turned) as f32;
+            let time = (fixed_elapsed(&app) - start_time).as_secs_f32();
+            measured = Some(time - (1.0 - fraction) * timestep(&app));
+        }
+        angle += turned;
+        previous = current;
+        min_distance = min_distance.min(current.length());
+        max_distance = max_distance.max(current.length());
+    }
+
+    let measured = measured.unwrap_or_else(|| panic!("{integrator:?} turned only {angle} rad"));
+    let period_error = (measured / ORBITS as f32 - period).abs() / period;
+    assert!(
+        period_error < 1e-3,
+        "{integrator:?} period {measured} vs {period}"
+    );
+    let eccentricity = (max_distance - min_distance) / (max_distance + min_distance);
+    // 位置 Verlet 由 LastPos 反推初速度, 少了 ½a·dt², 轨道略带偏心
+    assert!(
+        eccentricity < 2e-3,
+        "{integrator:?} eccentricity {eccentricity}"
+    );
+    // 整数圈之后回到起点附近
+    assert!(
+        end.distance(start) < 0.01 * SEPARATION,
+        "{integrator:?} ended at {end}, started at {start}"
+    );
+}
+
+#[test]
+fn two_body_orbit_position_verlet() {
+    check_two_body_orbit(Integrator::PositionVerlet);
+}
+
+#[test]
+fn two_body_orbit_velocity_verlet() {
+    check_two_body_orbit(Integrator::VelocityVerlet);
+}
+
+#[test]
+fn two_body_orbit_symplectic_euler() {
+    check_two_body_orbit(Integrator::SymplecticEuler);
+}
+
+#[test]
+fn two_body_orbit_runge_kutta4() {
+    check_two_body_orbit(Integrator::RungeKutta4);
+}
+
+// 引力两两大小相等方向相反, 碰撞合并也守恒动量, 总动量应当保持不变
+// 位置 Verlet 的速度由 f32 的位置差得到, 远处天体每步的 a·dt² 小于位置的精度会被舍掉,
+// 动量只能守恒到位置精度, 其余积分器直接累加速度, 只有求和的舍入误差
+#[test]
+fn total_momentum_is_conserved() {
+    for (integrator, tolerance) in [
+        (Integrator::PositionVerlet, 5e-3),
+        (Integrator::VelocityVerlet, 1e-5),
+        (Integrator::SymplecticEuler, 1e-5),
+        (Integrator::RungeKutta4, 1e-5),
+    ] {
+        let mut app = headless_app(Preset::RandomSphere.scenario(), integrator);
+        app.update();
+        let initial = bodies(&mut app);
+        let momentum = |bodies: &[(f32, Vec3, Vec3)]| -> Vec3 {
+            bodies
+                .iter()
+                .map(|(mass, _, velocity)| velocity * mass)
+                .sum()
+        };
+        let total_mass =
+            |bodies: &[(f32, Vec3, Vec3)]| -> f32 { bodies.iter().map(|(mass, ..)| mass).sum() };
+        // 误差相对于 Σm|v| 计算, 总动量本身可能接近 0
+        let scale: f32 = initial
+            .iter()
+            .map(|(mass, _, velocity)| mass * velocity.length())
+            .sum();
+
+        for _ in 0..500 {
+            app.update();
+        }
+        let bodies = bodies(&mut app);
+        // 500 步中发生了碰撞合并
+        assert!(
+            bodies.len() < initial.len(),
+            "{integrator:?} merged no bodies"
+        );
+        let drift = momentum(&bodies).distance(momentum(&initial));
+        assert!(
+            drift < tolerance * scale,
+            "{integrator:?} momentum drifted by {drift} (Σm|v| = {scale})"
+        );
+        let mass_error = (total_mass(&bodies) - total_mass(&initial)).abs();
+        assert!(mass_error < 1e-5 * total_mass(&initial));
+    }
+}
+
+// 幽灵模拟与真实模拟使用同样的 solver 和积分器, 预测的路径应当与之后真实的位置一致
+#[test]
+fn prediction_matches_simulation() {
+    const HORIZON: f32 = 5.0;
+    for integrator in [Integrator::PositionVerlet, Integrator::RungeKutta4] {
+        let mut app = headless_app(Preset::SunEarthMoon.scenario(), integrator);
+        app.insert_resource(OrbitPrediction::new(HORIZON));
+        app.update();
+        // 第二重的天体是地球
+        let world = app.world_mut();
+        let mut targets = world.query::<(Entity, &Mass)>();
+        let mut targets: Vec<(Entity, f32)> = targets
+            .iter(world)
+            .map(|(entity, mass)| (entity, mass.0))
+            .collect();
+        targets.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let earth = targets[1].0;
+        world.resource_mut::<OrbitPrediction>().select(Some(earth));
+        app.update();
+
+        let path = app.world().resource::<OrbitPrediction>().path().to_vec();
+        assert_eq!(path.len(), (HORIZON / timestep(&app)).ceil() as usize);
+        for (step, predicted) in path.iter().enumerate() {
+            app.update();
+            let actual = app.world().get::<Transform>(earth).unwrap().translation;
+            assert!(
+                actual.distance(*predicted) < 1e-4,
+                "{integrator:?} step {step}: predicted {predicted}, actual {actual}"
+            );
+            // 预测用完之前不会重新计算, 只是向前移动
+            let remaining = app.world().resource::<OrbitPrediction>().path();
+            assert_eq!(remaining, &path[(step + 1).min(path.len())..]);
+        }
+    }
+}
+
+// 子步数诊断按帧发布, 一帧执行多个固定步时累加, 不执行固定步时为 0
+#[test]
+fn substeps_are_measured_per_frame() {
+    let (scenario, _) = two_body_scenario();
+    let mut app = headless_app(scenario, Integrator::PositionVerlet);
+    let timestep = app.world().resource::<Time<Fixed>>().timestep();
+    let mut measure = |frame: Duration| {
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(frame));
+        app.update();
+        app.world()
+            .resource::<DiagnosticsStore>()
+            .get_measurement(&SUBSTEPS)
+            .map(|measurement| measurement.value)
+    };
+    // 第一帧时间不前进
+    measure(timestep);
+    assert_eq!(measure(timestep * 3), Some(3.0));
+    assert_eq!(measure(timestep / 4), Some(0.0));
+    assert_eq!(measure(timestep * 3 / 4), Some(1.0));
+}